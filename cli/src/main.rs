@@ -1,7 +1,14 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 
-use clap::Parser;
-use openmensa_parser_darmstadt::{openmensa, parser};
+use clap::{Parser, Subcommand, ValueEnum};
+use openmensa_parser_darmstadt::{
+    export,
+    filter::MealFilter,
+    graphql::menu_items::MenuItemsMenuItems,
+    history::History,
+    openmensa, parser, render,
+    search::{self, DishSearch},
+};
 use tokio::io::AsyncWriteExt;
 use tracing::level_filters::LevelFilter;
 
@@ -25,7 +32,9 @@ impl FromStr for FeedInput {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let parts: Vec<&str> = s.split(';').collect();
         if parts.len() < 5 {
-            anyhow::bail!("Feed format: canteen;name;priority;url;hour[;minute[;dayOfWeek[;dayOfMonth[;month[;retry]]]]]");
+            anyhow::bail!(
+                "Feed format: canteen;name;priority;url;hour[;minute[;dayOfWeek[;dayOfMonth[;month[;retry]]]]]"
+            );
         }
 
         Ok(Self {
@@ -38,36 +47,107 @@ impl FromStr for FeedInput {
             },
             url: parts[3].to_string(),
             hour: parts[4].to_string(),
-            minute: parts.get(5).filter(|&s| !s.is_empty()).map(|s| s.to_string()),
-            day_of_week: parts.get(6).filter(|&s| !s.is_empty()).map(|s| s.to_string()),
-            day_of_month: parts.get(7).filter(|&s| !s.is_empty()).map(|s| s.to_string()),
-            month: parts.get(8).filter(|&s| !s.is_empty()).map(|s| s.to_string()),
-            retry: parts.get(9).filter(|&s| !s.is_empty()).map(|s| s.to_string()),
+            minute: parts
+                .get(5)
+                .filter(|&s| !s.is_empty())
+                .map(|s| s.to_string()),
+            day_of_week: parts
+                .get(6)
+                .filter(|&s| !s.is_empty())
+                .map(|s| s.to_string()),
+            day_of_month: parts
+                .get(7)
+                .filter(|&s| !s.is_empty())
+                .map(|s| s.to_string()),
+            month: parts
+                .get(8)
+                .filter(|&s| !s.is_empty())
+                .map(|s| s.to_string()),
+            retry: parts
+                .get(9)
+                .filter(|&s| !s.is_empty())
+                .map(|s| s.to_string()),
         })
     }
 }
 
-impl Into<openmensa::Feed> for FeedInput {
-    fn into(self) -> openmensa::Feed {
+impl From<FeedInput> for openmensa::Feed {
+    fn from(value: FeedInput) -> Self {
         openmensa::Feed {
-            name: self.name,
-            priority: self.priority,
-            url: self.url,
+            name: value.name,
+            priority: value.priority,
+            url: value.url,
             source: None,
             schedule: Some(openmensa::Schedule {
-                hour: self.hour,
-                minute: self.minute,
-                day_of_week: self.day_of_week,
-                day_of_month: self.day_of_month,
-                month: self.month,
-                retry: self.retry,
+                hour: value.hour,
+                minute: value.minute,
+                day_of_week: value.day_of_week,
+                day_of_month: value.day_of_month,
+                month: value.month,
+                retry: value.retry,
             }),
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Xml,
+    Csv,
+}
+
+impl OutputFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Xml => "xml",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum TableFormat {
+    Table,
+    Csv,
+}
+
+#[derive(clap::Args, Debug, Clone)]
+struct FilterArgs {
+    #[arg(
+        long = "type",
+        value_delimiter = ',',
+        help = "Only include these dish types, e.g. vegan,meatless"
+    )]
+    types: Vec<String>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Skip meals containing these allergens, e.g. A,G,H"
+    )]
+    exclude_allergens: Vec<String>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Skip meals containing these additives, e.g. 9"
+    )]
+    exclude_additives: Vec<String>,
+}
+
+impl FilterArgs {
+    fn to_filter(&self) -> anyhow::Result<MealFilter> {
+        MealFilter::new(
+            &self.types,
+            &self.exclude_allergens,
+            &self.exclude_additives,
+        )
+    }
+}
+
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(short, long, required = true, num_args = 1..)]
     canteen: Vec<String>,
     #[arg(short, long)]
@@ -76,31 +156,273 @@ struct Args {
     to: Option<chrono::NaiveDate>,
     #[arg(short, long, default_value = "./out")]
     out: std::path::PathBuf,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Xml)]
+    format: OutputFormat,
     #[arg(
-        long, 
-        required = false, 
+        long,
+        required = false,
         num_args = 1..,
         help = "Feed format: CANTEEN;NAME;PRIORITY;URL;HOUR[;MINUTE[;DAY_OF_WEEK[;DAY_OF_MONTH[;MONTH[;RETRY]]]]]. Examples:\n  --feed \"1;full;1;https://openmensa.example.com/full/1.xml;4;*;*;*;60 5 1440\""
     )]
     feed: Vec<FeedInput>,
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[arg(
+        long,
+        global = true,
+        help = "Record fetched menu items in this SQLite database"
+    )]
+    history: Option<std::path::PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the menu of a single day to the terminal
+    Show {
+        #[arg(short, long)]
+        canteen: String,
+        #[arg(short, long, help = "Defaults to today")]
+        date: Option<chrono::NaiveDate>,
+        #[arg(long, help = "Render markdown instead of plain text")]
+        markdown: bool,
+        #[arg(short, long, help = "Include notes and labeled prices")]
+        verbose: bool,
+        #[arg(short, long, help = "Prefix meals with an emoji for their dish type")]
+        emoji: bool,
+        #[command(flatten)]
+        filter: FilterArgs,
+    },
+    /// Print recorded menus of a canteen or the price history of a dish, requires --history
+    History {
+        #[arg(short, long, required_unless_present = "dish")]
+        canteen: Option<String>,
+        #[arg(
+            long,
+            conflicts_with = "canteen",
+            help = "Print the price history of this dish"
+        )]
+        dish: Option<String>,
+        #[arg(short, long)]
+        from: Option<chrono::NaiveDate>,
+        #[arg(short, long)]
+        to: Option<chrono::NaiveDate>,
+        #[arg(long, value_enum, default_value_t = TableFormat::Table)]
+        format: TableFormat,
+    },
+    /// Search dish names in the upcoming menus and, with --history, the recorded ones
+    Search {
+        query: String,
+        #[arg(short, long, required = true, num_args = 1..)]
+        canteen: Vec<String>,
+        #[arg(
+            long = "type",
+            value_delimiter = ',',
+            help = "Only include these dish types"
+        )]
+        types: Vec<String>,
+        #[arg(short, long)]
+        from: Option<chrono::NaiveDate>,
+        #[arg(short, long)]
+        to: Option<chrono::NaiveDate>,
+        #[arg(long, value_enum, default_value_t = TableFormat::Table)]
+        format: TableFormat,
+    },
 }
 
-async fn fetch_and_write_canteen_data(
+/// Fetches the menu items of a canteen and records them in the history, if one is configured.
+async fn fetch_menu_items(
+    history: Option<&History>,
     canteen_id: String,
     from: Option<chrono::NaiveDate>,
     to: Option<chrono::NaiveDate>,
+) -> anyhow::Result<Vec<MenuItemsMenuItems>> {
+    let menu_items = parser::fetch_menu_items(canteen_id.clone(), from, to).await?;
+
+    if let Some(history) = history {
+        match history.record(&canteen_id, &menu_items) {
+            Ok(recorded) => tracing::debug!(
+                "recorded {} changed menu items of canteen \"{}\" in the history",
+                recorded,
+                canteen_id
+            ),
+            Err(e) => tracing::error!("failed to record menu history: {:?}", e),
+        }
+    }
+
+    Ok(menu_items)
+}
+
+async fn show_day(
+    history: Option<&History>,
+    canteen_id: String,
+    date: chrono::NaiveDate,
+    options: render::RenderOptions,
+    filter: MealFilter,
+) -> anyhow::Result<()> {
+    let menu_items = fetch_menu_items(history, canteen_id, Some(date), Some(date)).await?;
+    let data = parser::menu_items_to_openmensa(&filter.apply(&menu_items))?;
+
+    let date = date.to_string();
+    match data.canteen.days.iter().find(|d| d.date == date) {
+        Some(day) => print!("{}", render::render_day(day, &options)),
+        None => println!("no menu available for {}", date),
+    }
+
+    Ok(())
+}
+
+fn print_history(
+    history: Option<&History>,
+    canteen: Option<String>,
+    dish: Option<String>,
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+    format: TableFormat,
+) -> anyhow::Result<()> {
+    let history =
+        history.ok_or_else(|| anyhow::anyhow!("the history command requires --history"))?;
+    let price = |value: f64| format!("{:.2}", value);
+
+    if let Some(dish_id) = dish {
+        let dish = history
+            .dish(&dish_id)?
+            .ok_or_else(|| anyhow::anyhow!("dish {} was never recorded", dish_id))?;
+
+        match format {
+            TableFormat::Csv => print!("{}", export::to_csv(&dish.prices)?),
+            TableFormat::Table => {
+                println!(
+                    "{} ({}), served on {} days\n",
+                    dish.name,
+                    dish.dish_id,
+                    dish.served.len()
+                );
+                let rows = dish
+                    .prices
+                    .iter()
+                    .map(|p| {
+                        vec![
+                            p.canteen_id.clone(),
+                            p.since.to_string(),
+                            price(p.student_price),
+                            price(p.guest_price),
+                        ]
+                    })
+                    .collect::<Vec<_>>();
+                print!(
+                    "{}",
+                    render::render_table(&["canteen", "since", "student", "guest"], &rows)
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    let canteen =
+        canteen.ok_or_else(|| anyhow::anyhow!("either a canteen or a dish is required"))?;
+    let records = history.menu(&canteen, from, to)?;
+    match format {
+        TableFormat::Csv => print!("{}", export::to_csv(&records)?),
+        TableFormat::Table => {
+            let rows = records
+                .iter()
+                .map(|r| {
+                    vec![
+                        r.date.to_string(),
+                        r.dish_id.clone(),
+                        r.name.clone(),
+                        r.dish_type.clone(),
+                        price(r.student_price),
+                        price(r.guest_price),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            print!(
+                "{}",
+                render::render_table(&["date", "dish", "meal", "type", "student", "guest"], &rows)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn search_dishes(
+    history: Option<&History>,
+    search: DishSearch,
+    canteen_ids: Vec<String>,
+    format: TableFormat,
+) -> anyhow::Result<()> {
+    let today = chrono::Local::now().date_naive();
+    let mut upcoming = Vec::new();
+    for canteen_id in &canteen_ids {
+        let menu_items = fetch_menu_items(history, canteen_id.clone(), Some(today), None).await?;
+        upcoming.extend(search.search_menu(canteen_id, &menu_items)?);
+    }
+    let recorded = match history {
+        Some(history) => history.search(&search, &canteen_ids)?,
+        None => Vec::new(),
+    };
+    let hits = search::merge(upcoming, recorded);
+
+    match format {
+        TableFormat::Csv => print!("{}", export::to_csv(&hits)?),
+        TableFormat::Table if hits.is_empty() => println!("no matching dishes found"),
+        TableFormat::Table => {
+            let rows = hits
+                .iter()
+                .map(|h| {
+                    vec![
+                        h.date.to_string(),
+                        h.canteen_id.clone(),
+                        h.dish_id.clone(),
+                        h.name.clone(),
+                        h.dish_type.clone(),
+                        format!("{:.2}", h.student_price),
+                        format!("{:.2}", h.guest_price),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            print!(
+                "{}",
+                render::render_table(
+                    &[
+                        "date", "canteen", "dish", "meal", "type", "student", "guest"
+                    ],
+                    &rows
+                )
+            );
+        }
+    }
+
+    Ok(())
+}
+
+async fn write_canteen_data(
+    canteen_id: String,
+    menu_items: &[MenuItemsMenuItems],
     out: &std::path::PathBuf,
+    format: OutputFormat,
     feeds: Option<Vec<openmensa::Feed>>,
+    filter: MealFilter,
 ) -> anyhow::Result<()> {
-    let mut data = parser::fetch_openmensa_for_range(canteen_id.clone(), from, to).await?;
+    let menu_items = filter.apply(menu_items);
 
-    if let Some(feeds) = feeds {
-        data.canteen.feeds.extend(feeds);
-    }
+    let content = match format {
+        OutputFormat::Xml => {
+            let mut data = parser::menu_items_to_openmensa(&menu_items)?;
+
+            if let Some(feeds) = feeds {
+                data.canteen.feeds.extend(feeds);
+            }
+
+            data.serialize_to_string()?
+        }
+        OutputFormat::Csv => export::to_csv(&export::meal_records(&canteen_id, &menu_items)?)?,
+    };
 
     let mut file = tokio::fs::File::create(out).await?;
-    file.write_all(&data.serialize_to_string()?.as_bytes())
-        .await?;
+    file.write_all(content.as_bytes()).await?;
 
     tracing::debug!(
         "wrote data for canteen \"{}\" to {}",
@@ -112,13 +434,81 @@ async fn fetch_and_write_canteen_data(
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
+    let args = Args::parse();
+
     tracing_subscriber::fmt()
-        .with_max_level(LevelFilter::DEBUG)
+        .with_max_level(match args.command {
+            Some(_) => LevelFilter::WARN,
+            None => LevelFilter::DEBUG,
+        })
+        .with_writer(std::io::stderr)
         .init();
-
-    let args = Args::parse();
     tracing::debug!("args: {:?}", args);
 
+    let history = match args.history.as_deref().map(History::open).transpose() {
+        Ok(v) => v.map(Arc::new),
+        Err(e) => {
+            tracing::error!("{:?}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(command) = args.command {
+        let res = match command {
+            Command::Show {
+                canteen,
+                date,
+                markdown,
+                verbose,
+                emoji,
+                filter,
+            } => {
+                let options = render::RenderOptions {
+                    markup: match markdown {
+                        true => render::Markup::Markdown,
+                        false => render::Markup::Plain,
+                    },
+                    layout: match verbose {
+                        true => render::Layout::Verbose,
+                        false => render::Layout::Compact,
+                    },
+                    emoji,
+                };
+                let date = date.unwrap_or_else(|| chrono::Local::now().date_naive());
+                match filter.to_filter() {
+                    Ok(filter) => {
+                        show_day(history.as_deref(), canteen, date, options, filter).await
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            Command::History {
+                canteen,
+                dish,
+                from,
+                to,
+                format,
+            } => print_history(history.as_deref(), canteen, dish, from, to, format),
+            Command::Search {
+                query,
+                canteen,
+                types,
+                from,
+                to,
+                format,
+            } => match DishSearch::new(&query, &types, from, to) {
+                Ok(search) => search_dishes(history.as_deref(), search, canteen, format).await,
+                Err(e) => Err(e),
+            },
+        };
+
+        if let Err(e) = res {
+            tracing::error!("{:?}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     if let Err(e) = tokio::fs::create_dir_all(&args.out).await {
         tracing::error!(
             "failed to create \"{}\": {:?}",
@@ -128,37 +518,45 @@ async fn main() {
         panic!("failed to create out dir");
     }
 
+    let filter = match args.filter.to_filter() {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("invalid filter: {:?}", e);
+            std::process::exit(2);
+        }
+    };
+
     let mut set = tokio::task::JoinSet::new();
 
-    let feed_map: std::collections::HashMap<String, Vec<openmensa::Feed>> = args.feed.into_iter()
-    .map(|f| f.into()) // Convert to Feed
-    .fold(std::collections::HashMap::new(), |mut acc, feed_input: FeedInput| {
-        let feed = openmensa::Feed {
-            name: feed_input.name,
-            priority: feed_input.priority,
-            url: feed_input.url,
-            source: None,
-            schedule: Some(openmensa::Schedule {
-                hour: feed_input.hour,
-                minute: feed_input.minute,
-                day_of_week: feed_input.day_of_week,
-                day_of_month: feed_input.day_of_month,
-                month: feed_input.month,
-                retry: feed_input.retry,
-            }),
-        };
-        acc.entry(feed_input.canteen_id).or_insert_with(Vec::new).push(feed);
-        acc
-    });
+    let feed_map: std::collections::HashMap<String, Vec<openmensa::Feed>> =
+        args.feed.into_iter().fold(
+            std::collections::HashMap::new(),
+            |mut acc, feed_input: FeedInput| {
+                acc.entry(feed_input.canteen_id.clone())
+                    .or_insert_with(Vec::new)
+                    .push(feed_input.into());
+                acc
+            },
+        );
 
     for canteen_id in args.canteen {
-        let filename = args.out.join(format!("{canteen_id}.xml"));
+        let filename = args
+            .out
+            .join(format!("{canteen_id}.{}", args.format.extension()));
         let from = args.from;
         let to = args.to;
-        let feeds = feed_map.get(&canteen_id).map(|v| v.clone());
+        let format = args.format;
+        let feeds = feed_map.get(&canteen_id).cloned();
+        let filter = filter.clone();
+        let history = history.clone();
 
         set.spawn(async move {
-            if let Err(e) = fetch_and_write_canteen_data(canteen_id, from, to, &filename, feeds).await {
+            let res = async {
+                let menu_items =
+                    fetch_menu_items(history.as_deref(), canteen_id.clone(), from, to).await?;
+                write_canteen_data(canteen_id, &menu_items, &filename, format, feeds, filter).await
+            };
+            if let Err(e) = res.await {
                 tracing::error!("failed to fetch/write data: {:?}", e);
             }
         });
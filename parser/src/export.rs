@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+use crate::{graphql::menu_items, parser::item_date};
+
+/// A single meal flattened into one record, as used by the spreadsheet export.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MealRecord {
+    pub date: chrono::NaiveDate,
+    pub canteen: String,
+    pub category: String,
+    pub meal: String,
+    pub student_price: f64,
+    pub guest_price: f64,
+    pub dish_type: String,
+    pub allergens: String,
+    pub additives: String,
+    pub bio: bool,
+    pub mensa_vital: bool,
+}
+
+pub fn meal_records(
+    canteen_id: &str,
+    menu_items: &[menu_items::MenuItemsMenuItems],
+) -> anyhow::Result<Vec<MealRecord>> {
+    let mut records = menu_items
+        .iter()
+        .map(|item| {
+            let dish = &item.dish;
+
+            let mut allergens = dish.allergics.clone();
+            if let Some(specifics) = &dish.specific_allergics {
+                allergens.extend(specifics.iter().cloned());
+            }
+
+            Ok(MealRecord {
+                date: item_date(item)?,
+                canteen: canteen_id.to_string(),
+                category: "Mensa".into(),
+                meal: dish.name.clone(),
+                student_price: dish.student_price,
+                guest_price: dish.guest_price,
+                dish_type: dish_type_code(&dish.type_),
+                allergens: allergens.join(","),
+                additives: dish.additionals.join(","),
+                bio: dish.bio,
+                mensa_vital: dish.mensa_vital,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    records.sort_by_key(|r| r.date);
+    Ok(records)
+}
+
+pub fn dish_type_code(type_: &menu_items::DishType) -> String {
+    use menu_items::DishType;
+    match type_ {
+        DishType::VEGAN => "VEGAN".into(),
+        DishType::MEATLESS => "MEATLESS".into(),
+        DishType::PORK => "PORK".into(),
+        DishType::POULTRY => "POULTRY".into(),
+        DishType::FISH => "FISH".into(),
+        DishType::BEEF => "BEEF".into(),
+        DishType::UNKNOWN => "UNKNOWN".into(),
+        DishType::Other(other) => other.clone(),
+    }
+}
+
+/// Serializes a list as a comma separated string, so records with lists can be written as CSV.
+#[cfg(feature = "history")]
+pub(crate) fn serialize_list<S: serde::Serializer>(
+    list: &[String],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&list.join(","))
+}
+
+/// Serializes records to CSV with a header row of their serde field names, the same names the
+/// json output uses.
+pub fn to_csv<T: Serialize + Default>(records: &[T]) -> anyhow::Result<String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for record in records {
+        writer.serialize(record)?;
+    }
+    let csv = String::from_utf8(writer.into_inner()?)?;
+    if !records.is_empty() {
+        return Ok(csv);
+    }
+
+    // the header is only written along with the first record, keep it without the placeholder
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.serialize(T::default())?;
+    let placeholder = String::from_utf8(writer.into_inner()?)?;
+    Ok(placeholder
+        .split_inclusive('\n')
+        .next()
+        .unwrap_or_default()
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_uses_the_json_field_names() {
+        let record = MealRecord {
+            date: chrono::NaiveDate::from_ymd_opt(2026, 10, 19).unwrap(),
+            canteen: "1".into(),
+            category: "Mensa".into(),
+            meal: "Käsespätzle, \"hausgemacht\"".into(),
+            student_price: 3.5,
+            guest_price: 6.1,
+            dish_type: "VEGAN".into(),
+            allergens: "A,G".into(),
+            additives: String::new(),
+            bio: false,
+            mensa_vital: true,
+        };
+
+        assert_eq!(
+            to_csv(&[record]).unwrap(),
+            "date,canteen,category,meal,studentPrice,guestPrice,dishType,allergens,additives,bio,mensaVital\n\
+             2026-10-19,1,Mensa,\"Käsespätzle, \"\"hausgemacht\"\"\",3.5,6.1,VEGAN,\"A,G\",,false,true\n"
+        );
+    }
+
+    #[test]
+    fn empty_csv_keeps_the_header() {
+        assert_eq!(
+            to_csv::<MealRecord>(&[]).unwrap(),
+            "date,canteen,category,meal,studentPrice,guestPrice,dishType,allergens,additives,bio,mensaVital\n"
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{export::dish_type_code, graphql::menu_items};
+
+pub(crate) const DISH_TYPES: &[&str] = &[
+    "VEGAN", "MEATLESS", "PORK", "POULTRY", "FISH", "BEEF", "UNKNOWN",
+];
+
+/// Restricts menu items by dish type and excluded allergens or additives. All codes are kept
+/// upper case and sorted, so equal filters compare and hash equal.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct MealFilter {
+    types: Vec<String>,             // empty means every type
+    exclude_allergens: Vec<String>, // matched against allergics and specificAllergics
+    exclude_additives: Vec<String>,
+}
+
+impl MealFilter {
+    pub fn new(
+        types: &[String],
+        exclude_allergens: &[String],
+        exclude_additives: &[String],
+    ) -> anyhow::Result<Self> {
+        let types = normalize(types);
+        if let Some(unknown) = types.iter().find(|t| !DISH_TYPES.contains(&t.as_str())) {
+            anyhow::bail!(
+                "unknown dish type \"{}\", expected one of {}",
+                unknown.to_lowercase(),
+                DISH_TYPES.join(",").to_lowercase()
+            );
+        }
+
+        Ok(Self {
+            types,
+            exclude_allergens: normalize(exclude_allergens),
+            exclude_additives: normalize(exclude_additives),
+        })
+    }
+
+    /// Parses comma separated lists as used in query strings, e.g. `vegan,meatless`.
+    pub fn parse(
+        types: Option<&str>,
+        exclude_allergens: Option<&str>,
+        exclude_additives: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let split = |value: Option<&str>| -> Vec<String> {
+            value
+                .map(|v| v.split(',').map(|s| s.to_string()).collect())
+                .unwrap_or_default()
+        };
+
+        Self::new(
+            &split(types),
+            &split(exclude_allergens),
+            &split(exclude_additives),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.types.is_empty()
+            && self.exclude_allergens.is_empty()
+            && self.exclude_additives.is_empty()
+    }
+
+    pub fn matches(&self, item: &menu_items::MenuItemsMenuItems) -> bool {
+        let dish = &item.dish;
+
+        if !self.types.is_empty() && !self.types.contains(&dish_type_code(&dish.type_)) {
+            return false;
+        }
+
+        let excluded = |codes: &[String], code: &String| codes.contains(&code.to_uppercase());
+        let has_allergen = dish
+            .allergics
+            .iter()
+            .chain(dish.specific_allergics.iter().flatten())
+            .any(|a| excluded(&self.exclude_allergens, a));
+        let has_additive = dish
+            .additionals
+            .iter()
+            .any(|a| excluded(&self.exclude_additives, a));
+
+        !has_allergen && !has_additive
+    }
+
+    /// Keeps the matching menu items. Days without any remaining meal drop out of the generated
+    /// feed entirely, so the result stays valid OpenMensa.
+    pub fn apply(
+        &self,
+        menu_items: &[menu_items::MenuItemsMenuItems],
+    ) -> Vec<menu_items::MenuItemsMenuItems> {
+        menu_items
+            .iter()
+            .filter(|item| self.matches(item))
+            .cloned()
+            .collect()
+    }
+}
+
+pub(crate) fn normalize(codes: &[String]) -> Vec<String> {
+    let mut codes: Vec<String> = codes
+        .iter()
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    codes.sort_unstable();
+    codes.dedup();
+    codes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::menu_item;
+
+    fn dish(
+        type_: menu_items::DishType,
+        allergics: &[&str],
+        specific_allergics: Option<&[&str]>,
+        additionals: &[&str],
+    ) -> menu_items::MenuItemsMenuItems {
+        let codes = |codes: &[&str]| codes.iter().map(|c| c.to_string()).collect();
+        let mut item = menu_item("1", "2026-01-05", "10", "Käsespätzle", 3.5, 5.5);
+        item.dish.type_ = type_;
+        item.dish.allergics = codes(allergics);
+        item.dish.specific_allergics = specific_allergics.map(codes);
+        item.dish.additionals = codes(additionals);
+        item
+    }
+
+    fn filter(types: &str, allergens: &str, additives: &str) -> MealFilter {
+        MealFilter::parse(Some(types), Some(allergens), Some(additives)).unwrap()
+    }
+
+    #[test]
+    fn empty_filter_matches_everything() {
+        let filter = MealFilter::parse(None, None, None).unwrap();
+        assert!(filter.is_empty());
+        assert!(filter.matches(&dish(
+            menu_items::DishType::PORK,
+            &["GL"],
+            Some(&["Wz"]),
+            &["1"]
+        )));
+    }
+
+    #[test]
+    fn specific_allergics_are_excluded() {
+        let filter = filter("", "wz", "");
+        let item = dish(menu_items::DishType::VEGAN, &[], Some(&["Wz", "Ei"]), &[]);
+        assert!(!filter.matches(&item));
+        let item = dish(menu_items::DishType::VEGAN, &["GL"], Some(&["Ei"]), &[]);
+        assert!(filter.matches(&item));
+    }
+
+    #[test]
+    fn codes_match_case_insensitively() {
+        let filter = filter("", "gl", "2");
+        let item = dish(menu_items::DishType::VEGAN, &["Gl"], None, &[]);
+        assert!(!filter.matches(&item));
+        let item = dish(menu_items::DishType::VEGAN, &["gL"], None, &[]);
+        assert!(!filter.matches(&item));
+        let item = dish(menu_items::DishType::VEGAN, &[], None, &["2"]);
+        assert!(!filter.matches(&item));
+        let item = dish(menu_items::DishType::VEGAN, &["Ei"], None, &["3"]);
+        assert!(filter.matches(&item));
+    }
+
+    #[test]
+    fn types_include_and_exclude() {
+        let filter = filter("Vegan,meatless", "", "");
+        assert!(filter.matches(&dish(menu_items::DishType::VEGAN, &[], None, &[])));
+        assert!(filter.matches(&dish(menu_items::DishType::MEATLESS, &[], None, &[])));
+        assert!(!filter.matches(&dish(menu_items::DishType::PORK, &[], None, &[])));
+        assert!(!filter.matches(&dish(menu_items::DishType::FISH, &[], None, &[])));
+    }
+
+    #[test]
+    fn unknown_types_are_rejected() {
+        assert!(MealFilter::parse(Some("vegan,tofu"), None, None).is_err());
+    }
+}
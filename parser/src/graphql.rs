@@ -8,11 +8,30 @@ pub const MENSA_GRAPHQL_URL: &str = "https://mensa.k8s.incloud.de/graphql";
 #[graphql(
     schema_path = "graphql/schema.graphql",
     query_path = "graphql/menuitems.graphql",
-    response_derives = "Debug",
-    response_derives = "serde::Serialize"
+    response_derives = "Debug, Clone, PartialEq, Serialize"
 )]
 pub struct MenuItems;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "graphql/schema.graphql",
+    query_path = "graphql/locations.graphql",
+    response_derives = "Debug, Clone, Serialize"
+)]
+pub struct Locations;
+
+/// Classifies a failed query for metrics and logs.
+pub fn error_kind(error: &anyhow::Error) -> &'static str {
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) if e.is_timeout() => "timeout",
+        Some(e) if e.is_connect() => "connect",
+        Some(e) if e.is_status() => "status",
+        Some(e) if e.is_decode() => "decode",
+        Some(_) => "request",
+        None => "graphql", // errors reported in the graphql response
+    }
+}
+
 pub async fn send_query<T: GraphQLQuery>(
     variables: T::Variables,
 ) -> anyhow::Result<T::ResponseData> {
@@ -47,7 +66,18 @@ pub async fn send_query<T: GraphQLQuery>(
 }
 
 pub fn allergic_descriptive(allergic: &str) -> &str {
-    match allergic {
+    match allergic_name(allergic) {
+        Some(v) => v,
+        None => {
+            tracing::warn!("encountered unknown allergic: {}", allergic);
+            ""
+        }
+    }
+}
+
+/// Name of an allergen code, or `None` for codes this parser does not know.
+pub fn allergic_name(allergic: &str) -> Option<&'static str> {
+    let name = match allergic {
         "A" => "Glutenhaltiges Getreide",
         "A1" => "Weizen",
         "A2" => "Dinkel",
@@ -79,15 +109,25 @@ pub fn allergic_descriptive(allergic: &str) -> &str {
         "M" => "Lupine und Lupinenerzeugnisse",
         "N" => "Weichtiere (Mollusken)",
 
-        _ => {
-            tracing::warn!("encountered unknown allergic: {}", allergic);
+        _ => return None,
+    };
+
+    Some(name)
+}
+
+pub fn additive_descriptive(additive: &str) -> &str {
+    match additive_name(additive) {
+        Some(v) => v,
+        None => {
+            tracing::warn!("encountered unknown additive descriptive");
             ""
         }
     }
 }
 
-pub fn additive_descriptive(additive: &str) -> &str {
-    match additive {
+/// Name of an additive code, or `None` for codes this parser does not know.
+pub fn additive_name(additive: &str) -> Option<&'static str> {
+    let name = match additive {
         "1" => "Lebensmittelfarbe",
         "2" => "Konservierungsstoffe",
         "3" => "Antioxidationsmittel",
@@ -99,11 +139,10 @@ pub fn additive_descriptive(additive: &str) -> &str {
         "9" => "Süßungsmittel",
         "10" => "Phenylalaninquelle",
 
-        _ => {
-            tracing::warn!("encountered unknown additive descriptive");
-            ""
-        }
-    }
+        _ => return None,
+    };
+
+    Some(name)
 }
 
 pub fn type_descriptive(type_: &menu_items::DishType) -> Option<String> {
@@ -118,3 +157,16 @@ pub fn type_descriptive(type_: &menu_items::DishType) -> Option<String> {
         _ => None,
     }
 }
+
+/// Names of the known canteen ids, see `graphql/README.md`.
+pub fn canteen_name(canteen_id: &str) -> Option<&'static str> {
+    match canteen_id {
+        "1" => Some("Mensa Stadtmitte"),
+        "2" => Some("Mensa Lichtwiese"),
+        "3" => Some("Mensa Schöfferstrasse"),
+        "4" => Some("Mensa Dieburg"),
+        "5" => Some("Bistro Haardtring"),
+        "7" => Some("Schöffers Campusrestaurant"),
+        _ => None,
+    }
+}
@@ -0,0 +1,532 @@
+use std::{path::Path, sync::Mutex};
+
+use anyhow::Context;
+use rusqlite::{Connection, OptionalExtension, params, params_from_iter};
+use serde::Serialize;
+
+use crate::{
+    export::{dish_type_code, serialize_list},
+    graphql::menu_items,
+    parser::item_date,
+    search::{DishSearch, SearchHit, fold},
+};
+
+const SCHEMA_VERSION: i32 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS menu_items (
+    id INTEGER PRIMARY KEY,
+    item_id TEXT NOT NULL,
+    canteen_id TEXT NOT NULL,
+    date TEXT NOT NULL,
+    dish_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    folded_name TEXT NOT NULL, -- search::fold of the name, for searches
+    dish_type TEXT NOT NULL,
+    student_price REAL NOT NULL,
+    guest_price REAL NOT NULL,
+    allergens TEXT NOT NULL,
+    additives TEXT NOT NULL,
+    last_updated INTEGER NOT NULL,
+    recorded_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS menu_items_item_id ON menu_items (item_id);
+CREATE INDEX IF NOT EXISTS menu_items_canteen_date ON menu_items (canteen_id, date);
+";
+
+/// A menu item as stored in the history. A new revision is only recorded if one of these
+/// values changed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryRecord {
+    pub item_id: String,
+    pub canteen_id: String,
+    pub date: chrono::NaiveDate,
+    pub dish_id: String,
+    pub name: String,
+    pub dish_type: String,
+    pub student_price: f64,
+    pub guest_price: f64,
+    #[serde(serialize_with = "serialize_list")]
+    pub allergens: Vec<String>, // including specific allergens
+    #[serde(serialize_with = "serialize_list")]
+    pub additives: Vec<String>,
+    pub last_updated: i64, // newest upstream lastUpdated of the item and its dish
+}
+
+impl HistoryRecord {
+    pub fn new(canteen_id: &str, item: &menu_items::MenuItemsMenuItems) -> anyhow::Result<Self> {
+        let dish = &item.dish;
+        let mut allergens = dish.allergics.clone();
+        allergens.extend(dish.specific_allergics.iter().flatten().cloned());
+
+        Ok(Self {
+            item_id: item.id.clone(),
+            canteen_id: canteen_id.to_string(),
+            date: item_date(item)?,
+            dish_id: dish.id.clone(),
+            name: dish.name.clone(),
+            dish_type: dish_type_code(&dish.type_),
+            student_price: dish.student_price,
+            guest_price: dish.guest_price,
+            allergens,
+            additives: dish.additionals.clone(),
+            last_updated: item.last_updated.max(dish.last_updated),
+        })
+    }
+
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let list = |v: String| match v.is_empty() {
+            true => Vec::new(),
+            false => v.split(',').map(str::to_string).collect(),
+        };
+
+        Ok(Self {
+            item_id: row.get("item_id")?,
+            canteen_id: row.get("canteen_id")?,
+            date: row.get("date")?,
+            dish_id: row.get("dish_id")?,
+            name: row.get("name")?,
+            dish_type: row.get("dish_type")?,
+            student_price: row.get("student_price")?,
+            guest_price: row.get("guest_price")?,
+            allergens: list(row.get("allergens")?),
+            additives: list(row.get("additives")?),
+            last_updated: row.get("last_updated")?,
+        })
+    }
+}
+
+impl From<HistoryRecord> for SearchHit {
+    fn from(record: HistoryRecord) -> Self {
+        Self {
+            item_id: record.item_id,
+            canteen_id: record.canteen_id,
+            date: record.date,
+            dish_id: record.dish_id,
+            name: record.name,
+            dish_type: record.dish_type,
+            student_price: record.student_price,
+            guest_price: record.guest_price,
+            upcoming: false,
+        }
+    }
+}
+
+/// A dish being served on a day.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Serving {
+    pub date: chrono::NaiveDate,
+    pub canteen_id: String,
+}
+
+/// The prices of a dish at a canteen from the first day they were served at, as first recorded
+/// at `recorded_at`. A correction of an already published price shows up as a change at the time
+/// it was recorded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceChange {
+    pub canteen_id: String,
+    pub since: chrono::NaiveDate,
+    pub recorded_at: chrono::DateTime<chrono::Utc>,
+    pub student_price: f64,
+    pub guest_price: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DishHistory {
+    pub dish_id: String,
+    pub name: String, // as of the most recent serving
+    pub served: Vec<Serving>,
+    pub prices: Vec<PriceChange>, // ordered by canteen and recording time
+}
+
+// the latest revision of each menu item, earlier revisions were superseded by upstream changes
+const LATEST: &str =
+    "SELECT * FROM menu_items WHERE id IN (SELECT MAX(id) FROM menu_items GROUP BY item_id)";
+
+/// Persistent record of every fetched menu item in a SQLite database, so past menus stay
+/// available after upstream dropped them.
+pub struct History {
+    connection: Mutex<Connection>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("failed to open history database {}", path.display()))?;
+        // the cli and the server may write to the same database
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.busy_timeout(std::time::Duration::from_secs(5))?;
+
+        let version: i32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            anyhow::bail!(
+                "history database {} has schema version {}, newer than the supported version {}",
+                path.display(),
+                version,
+                SCHEMA_VERSION
+            );
+        }
+        connection.execute_batch(SCHEMA)?;
+        connection.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// Records the fetched menu items of a canteen, skipping items that did not change since
+    /// they were last recorded. Returns the number of recorded revisions.
+    pub fn record(
+        &self,
+        canteen_id: &str,
+        menu_items: &[menu_items::MenuItemsMenuItems],
+    ) -> anyhow::Result<usize> {
+        let records = menu_items
+            .iter()
+            .map(|item| HistoryRecord::new(canteen_id, item))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut connection = self.connection.lock().unwrap();
+        let tx = connection.transaction()?;
+        let recorded_at = chrono::Utc::now();
+        let mut recorded = 0;
+        {
+            let mut latest =
+                tx.prepare("SELECT * FROM menu_items WHERE item_id = ?1 ORDER BY id DESC LIMIT 1")?;
+            let mut insert = tx.prepare(
+                "INSERT INTO menu_items (item_id, canteen_id, date, dish_id, name, folded_name,
+                    dish_type, student_price, guest_price, allergens, additives, last_updated,
+                    recorded_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?;
+
+            for record in &records {
+                let previous = latest
+                    .query_row([&record.item_id], HistoryRecord::from_row)
+                    .optional()?;
+                if previous.as_ref() == Some(record) {
+                    continue;
+                }
+
+                insert.execute(params![
+                    record.item_id,
+                    record.canteen_id,
+                    record.date,
+                    record.dish_id,
+                    record.name,
+                    fold(&record.name),
+                    record.dish_type,
+                    record.student_price,
+                    record.guest_price,
+                    record.allergens.join(","),
+                    record.additives.join(","),
+                    record.last_updated,
+                    recorded_at,
+                ])?;
+                recorded += 1;
+            }
+        }
+        tx.commit()?;
+
+        Ok(recorded)
+    }
+
+    /// The menu of a canteen as last recorded, ordered by date and name.
+    pub fn menu(
+        &self,
+        canteen_id: &str,
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+    ) -> anyhow::Result<Vec<HistoryRecord>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "{} AND canteen_id = ?1 AND (?2 IS NULL OR date >= ?2) AND (?3 IS NULL OR date <= ?3)
+             ORDER BY date, name",
+            LATEST
+        ))?;
+        let records = statement
+            .query_map(params![canteen_id, from, to], HistoryRecord::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(records)
+    }
+
+    /// Where and when a dish was served and how its prices changed at each canteen, or `None`
+    /// if it was never recorded.
+    pub fn dish(&self, dish_id: &str) -> anyhow::Result<Option<DishHistory>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare(&format!(
+            "{} AND dish_id = ?1 ORDER BY date, canteen_id",
+            LATEST
+        ))?;
+        let records = statement
+            .query_map([dish_id], HistoryRecord::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let name = match records.last() {
+            Some(record) => record.name.clone(),
+            None => return Ok(None),
+        };
+
+        let mut served: Vec<Serving> = Vec::new();
+        for record in &records {
+            let serving = Serving {
+                date: record.date,
+                canteen_id: record.canteen_id.clone(),
+            };
+            if !served.contains(&serving) {
+                served.push(serving);
+            }
+        }
+
+        // every revision in the order it was recorded, so corrections of a published price are
+        // not lost
+        let mut statement = connection.prepare(
+            "SELECT canteen_id, date, recorded_at, student_price, guest_price FROM menu_items
+             WHERE dish_id = ?1 ORDER BY canteen_id, recorded_at, date, id",
+        )?;
+        let revisions = statement
+            .query_map([dish_id], |row| {
+                Ok(PriceChange {
+                    canteen_id: row.get("canteen_id")?,
+                    since: row.get("date")?,
+                    recorded_at: row.get("recorded_at")?,
+                    student_price: row.get("student_price")?,
+                    guest_price: row.get("guest_price")?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut prices: Vec<PriceChange> = Vec::new();
+        for revision in revisions {
+            let changed = prices.last().is_none_or(|last| {
+                last.canteen_id != revision.canteen_id
+                    || last.student_price != revision.student_price
+                    || last.guest_price != revision.guest_price
+            });
+            if changed {
+                prices.push(revision);
+            }
+        }
+
+        Ok(Some(DishHistory {
+            dish_id: dish_id.to_string(),
+            name,
+            served,
+            prices,
+        }))
+    }
+
+    /// The recorded menu items matching a search, in any of the given canteens or every canteen
+    /// if none are given.
+    pub fn search(
+        &self,
+        search: &DishSearch,
+        canteen_ids: &[String],
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let mut conditions = vec![
+            "(? IS NULL OR date >= ?)".to_string(),
+            "(? IS NULL OR date <= ?)".to_string(),
+        ];
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        for date in [search.from, search.to] {
+            let date = rusqlite::types::Value::from(date.map(|d| d.to_string()));
+            values.extend([date.clone(), date]);
+        }
+
+        let placeholders = |count: usize| vec!["?"; count].join(", ");
+        if !canteen_ids.is_empty() {
+            conditions.push(format!(
+                "canteen_id IN ({})",
+                placeholders(canteen_ids.len())
+            ));
+            values.extend(canteen_ids.iter().cloned().map(Into::into));
+        }
+        if !search.types.is_empty() {
+            conditions.push(format!(
+                "dish_type IN ({})",
+                placeholders(search.types.len())
+            ));
+            values.extend(search.types.iter().cloned().map(Into::into));
+        }
+        // folded terms only contain lower case letters, digits and spaces, nothing LIKE escapes
+        for term in &search.terms {
+            conditions.push("folded_name LIKE '%' || ? || '%'".to_string());
+            values.push(term.clone().into());
+        }
+
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare(&format!("{} AND {}", LATEST, conditions.join(" AND ")))?;
+        let records = statement
+            .query_map(params_from_iter(values), HistoryRecord::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(records.into_iter().map(SearchHit::from).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::menu_item;
+
+    fn history() -> History {
+        History::open(Path::new(":memory:")).unwrap()
+    }
+
+    #[test]
+    fn record_only_inserts_changed_items() {
+        let history = history();
+        let items = vec![
+            menu_item("1", "2026-01-05", "10", "Käsespätzle", 3.5, 5.5),
+            menu_item("2", "2026-01-05", "11", "Currywurst", 3.0, 5.0),
+        ];
+        assert_eq!(history.record("1", &items).unwrap(), 2);
+        assert_eq!(history.record("1", &items).unwrap(), 0);
+
+        let mut changed = items.clone();
+        changed[0].dish.student_price = 3.8;
+        assert_eq!(history.record("1", &changed).unwrap(), 1);
+
+        let menu = history.menu("1", None, None).unwrap();
+        assert_eq!(menu.len(), 2);
+        assert_eq!(menu[1].name, "Käsespätzle");
+        assert_eq!(menu[1].student_price, 3.8);
+    }
+
+    #[test]
+    fn prices_change_per_canteen() {
+        let history = history();
+        let days = ["2026-01-05", "2026-01-06", "2026-01-07"];
+        for (i, date) in days.iter().enumerate() {
+            let id = i.to_string();
+            history
+                .record("1", &[menu_item(&id, date, "10", "Chili", 3.5, 5.5)])
+                .unwrap();
+            history
+                .record(
+                    "2",
+                    &[menu_item(
+                        &format!("2{}", id),
+                        date,
+                        "10",
+                        "Chili",
+                        4.0,
+                        6.0,
+                    )],
+                )
+                .unwrap();
+        }
+
+        let dish = history.dish("10").unwrap().unwrap();
+        assert_eq!(dish.served.len(), 6);
+        let prices: Vec<(&str, f64)> = dish
+            .prices
+            .iter()
+            .map(|p| (p.canteen_id.as_str(), p.student_price))
+            .collect();
+        assert_eq!(prices, [("1", 3.5), ("2", 4.0)]);
+    }
+
+    #[test]
+    fn price_corrections_are_reported() {
+        let history = history();
+        let items = vec![
+            menu_item("1", "2026-01-05", "10", "Chili", 3.5, 5.5),
+            menu_item("2", "2026-01-06", "10", "Chili", 3.5, 5.5),
+        ];
+        history.record("1", &items).unwrap();
+
+        let mut corrected = items.clone();
+        corrected[0].dish.student_price = 3.8;
+        corrected[1].dish.student_price = 3.8;
+        history.record("1", &corrected).unwrap();
+
+        let dish = history.dish("10").unwrap().unwrap();
+        let prices: Vec<(String, f64)> = dish
+            .prices
+            .iter()
+            .map(|p| (p.since.to_string(), p.student_price))
+            .collect();
+        assert_eq!(
+            prices,
+            [
+                ("2026-01-05".to_string(), 3.5),
+                ("2026-01-05".to_string(), 3.8)
+            ]
+        );
+        assert!(dish.prices[0].recorded_at < dish.prices[1].recorded_at);
+    }
+
+    #[test]
+    fn records_are_written_as_csv() {
+        let history = history();
+        let mut item = menu_item("1", "2026-01-05", "10", "Chili", 3.5, 5.5);
+        item.dish.allergics = vec!["Gl".into(), "Sl".into()];
+        history.record("1", &[item]).unwrap();
+
+        let csv = crate::export::to_csv(&history.menu("1", None, None).unwrap()).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some(
+                "itemId,canteenId,date,dishId,name,dishType,studentPrice,guestPrice,allergens,\
+                 additives,lastUpdated"
+            )
+        );
+        assert_eq!(
+            lines.next(),
+            Some("1,1,2026-01-05,10,Chili,MEATLESS,3.5,5.5,\"Gl,Sl\",,0")
+        );
+    }
+
+    #[test]
+    fn search_filters_in_the_database() {
+        let history = history();
+        history
+            .record(
+                "1",
+                &[
+                    menu_item("1", "2026-01-05", "10", "Käsespätzle", 3.5, 5.5),
+                    menu_item("2", "2026-01-06", "11", "Queue of Käse", 3.5, 5.5),
+                ],
+            )
+            .unwrap();
+        history
+            .record(
+                "2",
+                &[menu_item("3", "2026-01-05", "10", "Käsespätzle", 3.5, 5.5)],
+            )
+            .unwrap();
+
+        let items = |search: DishSearch, canteen_ids: &[String]| {
+            let mut ids: Vec<String> = history
+                .search(&search, canteen_ids)
+                .unwrap()
+                .into_iter()
+                .map(|hit| hit.item_id)
+                .collect();
+            ids.sort();
+            ids
+        };
+        let date = |date: &str| Some(date.parse().unwrap());
+
+        let search = DishSearch::new("KAESE", &[], None, None).unwrap();
+        assert_eq!(items(search.clone(), &[]), ["1", "2", "3"]);
+        assert_eq!(items(search.clone(), &["2".into()]), ["3"]);
+
+        let search = DishSearch::new("kaese", &[], date("2026-01-06"), None).unwrap();
+        assert_eq!(items(search, &[]), ["2"]);
+        let search = DishSearch::new("spätzle", &[], None, date("2026-01-05")).unwrap();
+        assert_eq!(items(search, &["1".into()]), ["1"]);
+        let search = DishSearch::new("kaese", &["vegan".into()], None, None).unwrap();
+        assert!(items(search, &[]).is_empty());
+        let search = DishSearch::new("que", &["meatless".into()], None, None).unwrap();
+        assert_eq!(items(search, &[]), ["2"]);
+    }
+}
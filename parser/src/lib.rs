@@ -1,3 +1,11 @@
+pub mod export;
+pub mod filter;
 pub mod graphql;
+#[cfg(feature = "history")]
+pub mod history;
 pub mod openmensa;
 pub mod parser;
+pub mod render;
+pub mod search;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
@@ -11,6 +11,15 @@ pub struct OpenMensa {
 }
 
 impl OpenMensa {
+    /// A version 2.1 document of this parser version.
+    pub fn new(canteen: Canteen) -> Self {
+        Self {
+            version: "2.1".into(),
+            parser_version: option_env!("CARGO_PKG_VERSION").map(|v| v.into()),
+            canteen,
+        }
+    }
+
     pub fn serialize_to_string(&self) -> anyhow::Result<String> {
         let mut buf = Vec::<u8>::new();
         let cursor = std::io::Cursor::new(&mut buf);
@@ -30,13 +39,11 @@ impl OpenMensa {
             .write_inner_content(|w| {
                 if let Some(parser_version) = &self.parser_version {
                     w.write_serializable("version", parser_version)
-                        .map_err(|e| {
-                            std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
-                        })?;
+                        .map_err(|e| std::io::Error::other(e.to_string()))?;
                 }
 
                 w.write_serializable("canteen", &self.canteen)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
 
                 Ok(())
             })?;
@@ -86,7 +93,7 @@ pub struct Location {
     pub longitude: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Availability {
     Public,
@@ -216,7 +223,7 @@ pub struct Price {
     pub role: PriceRole,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum PriceRole {
     Pupil,
@@ -2,16 +2,17 @@ use std::collections::HashMap;
 
 use crate::{
     graphql::{
-        self, MenuItems, additive_descriptive, allergic_descriptive, menu_items, type_descriptive,
+        self, Locations, MenuItems, additive_descriptive, allergic_descriptive, locations,
+        menu_items, type_descriptive,
     },
     openmensa,
 };
 
-pub async fn fetch_openmensa_for_range(
+pub async fn fetch_menu_items(
     canteen_id: String,
     from_date: Option<chrono::NaiveDate>,
     to_date: Option<chrono::NaiveDate>,
-) -> anyhow::Result<openmensa::OpenMensa> {
+) -> anyhow::Result<Vec<menu_items::MenuItemsMenuItems>> {
     let from_date = from_date.map(|v| v.to_string());
     let to_date = to_date.map(|v| v.to_string());
 
@@ -24,17 +25,42 @@ pub async fn fetch_openmensa_for_range(
     .await?
     .menu_items;
 
-    let mut grouped_items: HashMap<chrono::NaiveDate, Vec<menu_items::MenuItemsMenuItemsDish>> =
-        HashMap::new();
-    for item in menu_items {
-        let date = chrono::DateTime::<chrono::Utc>::from_timestamp_secs(item.date)
+    Ok(menu_items)
+}
+
+pub async fn fetch_locations() -> anyhow::Result<Vec<locations::LocationsLocations>> {
+    Ok(graphql::send_query::<Locations>(locations::Variables {})
+        .await?
+        .locations)
+}
+
+pub async fn fetch_openmensa_for_range(
+    canteen_id: String,
+    from_date: Option<chrono::NaiveDate>,
+    to_date: Option<chrono::NaiveDate>,
+) -> anyhow::Result<openmensa::OpenMensa> {
+    let menu_items = fetch_menu_items(canteen_id, from_date, to_date).await?;
+    menu_items_to_openmensa(&menu_items)
+}
+
+pub fn item_date(item: &menu_items::MenuItemsMenuItems) -> anyhow::Result<chrono::NaiveDate> {
+    Ok(
+        chrono::DateTime::<chrono::Utc>::from_timestamp_secs(item.date)
             .ok_or(anyhow::anyhow!("failed to decode item date"))?
-            .date_naive();
+            .date_naive(),
+    )
+}
 
+pub fn menu_items_to_openmensa(
+    menu_items: &[menu_items::MenuItemsMenuItems],
+) -> anyhow::Result<openmensa::OpenMensa> {
+    let mut grouped_items: HashMap<chrono::NaiveDate, Vec<&menu_items::MenuItemsMenuItemsDish>> =
+        HashMap::new();
+    for item in menu_items {
         grouped_items
-            .entry(date)
-            .or_insert_with(Vec::new)
-            .push(item.dish);
+            .entry(item_date(item)?)
+            .or_default()
+            .push(&item.dish);
     }
 
     let mut grouped_items = grouped_items.iter().collect::<Vec<_>>();
@@ -77,41 +103,37 @@ pub async fn fetch_openmensa_for_range(
         notes
     };
 
-    let openmensa = openmensa::OpenMensa {
-        version: "2.1".into(),
-        parser_version: option_env!("CARGO_PKG_VERSION").map(|v| v.into()),
-        canteen: openmensa::Canteen {
-            days: grouped_items
-                .iter()
-                .map(|(date, dishes)| openmensa::Day {
-                    date: date.to_string(),
-                    content: openmensa::DayContent::Open {
-                        category: vec![openmensa::Category {
-                            name: "Mensa".into(),
-                            meal: dishes
-                                .iter()
-                                .map(|dish| openmensa::Meal {
-                                    name: dish.name.clone(),
-                                    notes: notes(&dish),
-                                    prices: vec![
-                                        openmensa::Price {
-                                            role: openmensa::PriceRole::Student,
-                                            value: dish.student_price as f32,
-                                        },
-                                        openmensa::Price {
-                                            role: openmensa::PriceRole::Other,
-                                            value: dish.guest_price as f32,
-                                        },
-                                    ],
-                                })
-                                .collect(),
-                        }],
-                    },
-                })
-                .collect::<Vec<_>>(),
-            ..Default::default()
-        },
-    };
+    let openmensa = openmensa::OpenMensa::new(openmensa::Canteen {
+        days: grouped_items
+            .iter()
+            .map(|(date, dishes)| openmensa::Day {
+                date: date.to_string(),
+                content: openmensa::DayContent::Open {
+                    category: vec![openmensa::Category {
+                        name: "Mensa".into(),
+                        meal: dishes
+                            .iter()
+                            .map(|dish| openmensa::Meal {
+                                name: dish.name.clone(),
+                                notes: notes(dish),
+                                prices: vec![
+                                    openmensa::Price {
+                                        role: openmensa::PriceRole::Student,
+                                        value: dish.student_price as f32,
+                                    },
+                                    openmensa::Price {
+                                        role: openmensa::PriceRole::Other,
+                                        value: dish.guest_price as f32,
+                                    },
+                                ],
+                            })
+                            .collect(),
+                    }],
+                },
+            })
+            .collect::<Vec<_>>(),
+        ..Default::default()
+    });
 
     Ok(openmensa)
 }
@@ -0,0 +1,497 @@
+use std::fmt::Write;
+
+use crate::openmensa::{self, DayContent, PriceRole};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Markup {
+    #[default]
+    Plain,
+    Markdown,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Layout {
+    #[default]
+    Compact,
+    Verbose,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderOptions {
+    pub markup: Markup,
+    pub layout: Layout,
+    pub emoji: bool,
+}
+
+/// Renders a single day as plain text or markdown, e.g. for chat bots or terminals.
+pub fn render_day(day: &openmensa::Day, options: &RenderOptions) -> String {
+    let mut out = String::new();
+
+    match (options.markup, options.layout) {
+        (Markup::Plain, _) => {
+            let _ = writeln!(out, "{}", day.date);
+        }
+        (Markup::Markdown, Layout::Compact) => {
+            let _ = writeln!(out, "**{}**", day.date);
+        }
+        (Markup::Markdown, Layout::Verbose) => {
+            let _ = writeln!(out, "## {}", day.date);
+        }
+    }
+
+    let categories = match &day.content {
+        DayContent::Open { category } => category,
+        DayContent::Closed { .. } => {
+            out.push('\n');
+            out.push_str("geschlossen\n");
+            return out;
+        }
+    };
+
+    for category in categories {
+        out.push('\n');
+        match (options.markup, options.layout) {
+            (Markup::Plain, _) => {
+                let _ = writeln!(out, "{}", category.name);
+            }
+            (Markup::Markdown, Layout::Compact) => {
+                let _ = writeln!(out, "*{}*", escape_markdown(&category.name));
+            }
+            (Markup::Markdown, Layout::Verbose) => {
+                let _ = writeln!(out, "### {}", escape_markdown(&category.name));
+            }
+        }
+
+        for meal in &category.meal {
+            render_meal(&mut out, meal, options);
+        }
+    }
+
+    out
+}
+
+fn render_meal(out: &mut String, meal: &openmensa::Meal, options: &RenderOptions) {
+    let bullet = match options.markup {
+        Markup::Plain => "  ",
+        Markup::Markdown => "- ",
+    };
+    let indent = match options.markup {
+        Markup::Plain => "    ",
+        Markup::Markdown => "  ",
+    };
+
+    let mut name = match options.markup {
+        Markup::Plain => meal.name.clone(),
+        Markup::Markdown => escape_markdown(&meal.name),
+    };
+    if options.layout == Layout::Verbose && options.markup == Markup::Markdown {
+        name = format!("**{}**", name);
+    }
+    if options.emoji
+        && let Some(emoji) = meal.notes.iter().find_map(|n| type_emoji(n))
+    {
+        name = format!("{} {}", emoji, name);
+    }
+
+    match options.layout {
+        Layout::Compact => {
+            let prices = [PriceRole::Student, PriceRole::Other]
+                .iter()
+                .filter_map(|role| price(meal, role))
+                .map(format_price)
+                .collect::<Vec<_>>();
+
+            if prices.is_empty() {
+                let _ = writeln!(out, "{}{}", bullet, name);
+            } else {
+                let _ = writeln!(out, "{}{} ({})", bullet, name, prices.join(" / "));
+            }
+        }
+        Layout::Verbose => {
+            let _ = writeln!(out, "{}{}", bullet, name);
+
+            let notes = meal
+                .notes
+                .iter()
+                .filter(|n| !n.is_empty())
+                .map(|n| match options.markup {
+                    Markup::Plain => n.clone(),
+                    Markup::Markdown => escape_markdown(n),
+                })
+                .collect::<Vec<_>>();
+            if !notes.is_empty() {
+                let _ = writeln!(out, "{}{}", indent, notes.join(", "));
+            }
+
+            let prices = meal
+                .prices
+                .iter()
+                .map(|p| format!("{}: {}", role_label(&p.role), format_price(p.value)))
+                .collect::<Vec<_>>();
+            if !prices.is_empty() {
+                let _ = writeln!(out, "{}{}", indent, prices.join(" · "));
+            }
+        }
+    }
+}
+
+fn price(meal: &openmensa::Meal, role: &PriceRole) -> Option<f32> {
+    meal.prices
+        .iter()
+        .find(|p| p.role == *role)
+        .map(|p| p.value)
+}
+
+fn format_price(value: f32) -> String {
+    format!("{:.2} €", value).replace('.', ",")
+}
+
+fn role_label(role: &PriceRole) -> &'static str {
+    match role {
+        PriceRole::Pupil => "Schüler",
+        PriceRole::Student => "Studierende",
+        PriceRole::Employee => "Bedienstete",
+        PriceRole::Other => "Gäste",
+    }
+}
+
+/// Maps the dish type notes produced by [`crate::graphql::type_descriptive`] to an emoji.
+fn type_emoji(note: &str) -> Option<&'static str> {
+    match note {
+        "Vegan" => Some("🌱"),
+        "Vegetarisch" => Some("🥕"),
+        "Schweinefleisch" => Some("🐖"),
+        "Geflügel" => Some("🐔"),
+        "Fisch" => Some("🐟"),
+        "Rind" => Some("🐄"),
+        _ => None,
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '~') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Renders days as a standalone html page, e.g. for digital signage.
+pub fn render_html(title: &str, days: &[openmensa::Day]) -> String {
+    let mut out = String::new();
+
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"de\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>\
+         body{{font-family:sans-serif;margin:2em;}}\
+         .notes{{color:#666;font-size:.9em;}}\
+         .prices{{float:right;}}\
+         li{{margin-bottom:.5em;}}\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n",
+        title = escape_html(title)
+    );
+
+    if days.is_empty() {
+        out.push_str("<p>Kein Speiseplan verfügbar</p>\n");
+    }
+
+    for day in days {
+        let _ = writeln!(
+            out,
+            "<section>\n<h2><time datetime=\"{date}\">{date}</time></h2>",
+            date = escape_html(&day.date)
+        );
+
+        match &day.content {
+            DayContent::Closed { .. } => out.push_str("<p>geschlossen</p>\n"),
+            DayContent::Open { category } => {
+                for category in category {
+                    let _ = writeln!(out, "<h3>{}</h3>\n<ul>", escape_html(&category.name));
+
+                    for meal in &category.meal {
+                        let prices = [PriceRole::Student, PriceRole::Other]
+                            .iter()
+                            .filter_map(|role| price(meal, role))
+                            .map(format_price)
+                            .collect::<Vec<_>>();
+                        let notes = meal
+                            .notes
+                            .iter()
+                            .filter(|n| !n.is_empty())
+                            .map(|n| escape_html(n))
+                            .collect::<Vec<_>>();
+
+                        let _ = writeln!(
+                            out,
+                            "<li><span class=\"prices\">{}</span><span class=\"meal\">{}</span>\
+                             <br><span class=\"notes\">{}</span></li>",
+                            prices.join(" / "),
+                            escape_html(&meal.name),
+                            notes.join(", ")
+                        );
+                    }
+
+                    out.push_str("</ul>\n");
+                }
+            }
+        }
+
+        out.push_str("</section>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Renders days as an iCalendar file with one all-day event per open day, listing its meals in
+/// the description. `stamp` is used as DTSTAMP so the output only changes with the data.
+pub fn render_ics(
+    identifier: &str,
+    days: &[openmensa::Day],
+    stamp: chrono::DateTime<chrono::Utc>,
+) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//openmensa-parser-darmstadt//DE".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!(
+            "X-WR-CALNAME:{}",
+            escape_ics(&format!("Speiseplan {}", identifier))
+        ),
+    ];
+
+    for day in days {
+        let category = match &day.content {
+            DayContent::Open { category } => category,
+            DayContent::Closed { .. } => continue,
+        };
+        let date = match chrono::NaiveDate::parse_from_str(&day.date, "%Y-%m-%d") {
+            Ok(v) => v,
+            Err(_) => continue,
+        };
+
+        let meals = category
+            .iter()
+            .flat_map(|c| &c.meal)
+            .map(|meal| {
+                let prices = [PriceRole::Student, PriceRole::Other]
+                    .iter()
+                    .filter_map(|role| price(meal, role))
+                    .map(format_price)
+                    .collect::<Vec<_>>();
+                match prices.is_empty() {
+                    true => meal.name.clone(),
+                    false => format!("{} ({})", meal.name, prices.join(" / ")),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!(
+                "UID:{}-{}@openmensa-parser-darmstadt",
+                date.format("%Y%m%d"),
+                identifier
+            ),
+            format!("DTSTAMP:{}", stamp.format("%Y%m%dT%H%M%SZ")),
+            format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+            format!(
+                "DTEND;VALUE=DATE:{}",
+                (date + chrono::Days::new(1)).format("%Y%m%d")
+            ),
+            format!(
+                "SUMMARY:{}",
+                escape_ics(&format!("Speiseplan {}", identifier))
+            ),
+            format!("DESCRIPTION:{}", escape_ics(&meals.join("\n"))),
+            "TRANSP:TRANSPARENT".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = String::new();
+    for line in lines {
+        fold_ics_line(&mut out, &line);
+    }
+    out
+}
+
+fn escape_ics(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folded after 75 octets as required by RFC 5545.
+fn fold_ics_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+        out.push(c);
+        length += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Renders rows as a plain text table with left aligned, space padded columns.
+pub fn render_table(header: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths = header.iter().map(|h| h.chars().count()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let separator = widths.iter().map(|w| "-".repeat(*w)).collect::<Vec<_>>();
+    let lines = std::iter::once(header.iter().map(|h| h.to_string()).collect::<Vec<_>>())
+        .chain(std::iter::once(separator))
+        .chain(rows.iter().cloned());
+
+    let mut out = String::new();
+    for cells in lines {
+        let line = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ");
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::menu_items_to_openmensa, testing::menu_item};
+
+    fn day() -> openmensa::Day {
+        let data = menu_items_to_openmensa(&[
+            menu_item("1", "2026-01-05", "10", "Käsespätzle", 3.5, 5.5),
+            menu_item("2", "2026-01-05", "11", "Chili *scharf*", 4.0, 6.0),
+        ])
+        .unwrap();
+        data.canteen.days.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn render_day_as_plain_text() {
+        let options = RenderOptions::default();
+        assert_eq!(
+            render_day(&day(), &options),
+            "2026-01-05\n\nMensa\n  Käsespätzle (3,50 € / 5,50 €)\n  Chili *scharf* (4,00 € / 6,00 €)\n"
+        );
+    }
+
+    #[test]
+    fn render_day_as_verbose_markdown() {
+        let options = RenderOptions {
+            markup: Markup::Markdown,
+            layout: Layout::Verbose,
+            emoji: true,
+        };
+        assert_eq!(
+            render_day(&day(), &options),
+            "## 2026-01-05\n\n### Mensa\n\
+             - 🥕 **Käsespätzle**\n  Vegetarisch\n  Studierende: 3,50 € · Gäste: 5,50 €\n\
+             - 🥕 **Chili \\*scharf\\***\n  Vegetarisch\n  Studierende: 4,00 € · Gäste: 6,00 €\n"
+        );
+    }
+
+    #[test]
+    fn render_day_when_closed() {
+        let day = openmensa::Day {
+            date: "2026-01-05".into(),
+            content: DayContent::Closed {
+                closed: openmensa::Empty {},
+            },
+        };
+        assert_eq!(
+            render_day(&day, &RenderOptions::default()),
+            "2026-01-05\n\ngeschlossen\n"
+        );
+    }
+
+    #[test]
+    fn render_ics_uses_crlf_line_endings() {
+        let stamp = chrono::DateTime::from_timestamp(1767225600, 0).unwrap();
+        let ics = render_ics("mensa", &[day()], stamp);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(!ics.replace("\r\n", "").contains('\n'));
+        assert!(ics.contains("\r\nDTSTART;VALUE=DATE:20260105\r\n"));
+        assert!(ics.contains("\r\nDTEND;VALUE=DATE:20260106\r\n"));
+        assert!(ics.contains("\r\nDTSTAMP:20260101T000000Z\r\n"));
+        assert!(ics.contains("Käsespätzle (3\\,50 € / 5\\,50 €)\\nChili"));
+    }
+
+    #[test]
+    fn fold_ics_line_keeps_short_lines() {
+        let mut out = String::new();
+        fold_ics_line(&mut out, &"a".repeat(75));
+        assert_eq!(out, format!("{}\r\n", "a".repeat(75)));
+    }
+
+    #[test]
+    fn fold_ics_line_folds_after_75_octets() {
+        let mut out = String::new();
+        fold_ics_line(&mut out, &"a".repeat(160));
+        assert_eq!(
+            out,
+            format!(
+                "{}\r\n {}\r\n {}\r\n",
+                "a".repeat(75),
+                "a".repeat(74),
+                "a".repeat(11)
+            )
+        );
+    }
+
+    #[test]
+    fn fold_ics_line_never_splits_characters() {
+        // 'ä' takes two octets, the 38th would end at octet 76
+        let mut out = String::new();
+        fold_ics_line(&mut out, &"ä".repeat(40));
+        assert_eq!(out, format!("{}\r\n {}\r\n", "ä".repeat(37), "ä".repeat(3)));
+        for line in out.split("\r\n") {
+            assert!(line.len() <= 75);
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::{
+    export::dish_type_code,
+    filter::{DISH_TYPES, normalize},
+    graphql::menu_items,
+    parser::item_date,
+};
+
+/// A menu item matching a search, either from the upcoming menus or the history.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub item_id: String,
+    pub canteen_id: String,
+    pub date: chrono::NaiveDate,
+    pub dish_id: String,
+    pub name: String,
+    pub dish_type: String,
+    pub student_price: f64,
+    pub guest_price: f64,
+    pub upcoming: bool, // found in the current upstream menu rather than only in the history
+}
+
+/// Folds text for matching: lower case, umlauts expanded to their transcription, other accents
+/// removed and punctuation replaced by spaces. "Käsespätzle" and "KAESESPAETZLE" fold equal.
+/// The history stores folded names, changes here need a new history schema version.
+pub fn fold(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        match c {
+            'ä' => folded.push_str("ae"),
+            'ö' => folded.push_str("oe"),
+            'ü' => folded.push_str("ue"),
+            'ß' => folded.push_str("ss"),
+            'á' | 'à' | 'â' => folded.push('a'),
+            'ó' | 'ò' | 'ô' => folded.push('o'),
+            'ú' | 'ù' | 'û' => folded.push('u'),
+            'é' | 'è' | 'ê' => folded.push('e'),
+            c if c.is_alphanumeric() => folded.push(c),
+            _ => folded.push(' '),
+        }
+    }
+    folded
+}
+
+/// Searches dish names for all words of a query, restricted by dish type and date.
+#[derive(Debug, Clone)]
+pub struct DishSearch {
+    pub(crate) terms: Vec<String>, // folded
+    pub(crate) types: Vec<String>, // empty means every type
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+}
+
+impl DishSearch {
+    pub fn new(
+        query: &str,
+        types: &[String],
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+    ) -> anyhow::Result<Self> {
+        let terms: Vec<String> = fold(query).split_whitespace().map(str::to_string).collect();
+        if terms.is_empty() {
+            anyhow::bail!("the search query must contain at least one word");
+        }
+
+        let types = normalize(types);
+        if let Some(unknown) = types.iter().find(|t| !DISH_TYPES.contains(&t.as_str())) {
+            anyhow::bail!(
+                "unknown dish type \"{}\", expected one of {}",
+                unknown.to_lowercase(),
+                DISH_TYPES.join(",").to_lowercase()
+            );
+        }
+
+        if let (Some(from), Some(to)) = (from, to)
+            && to < from
+        {
+            anyhow::bail!("the end of the date range must not be before its start");
+        }
+
+        Ok(Self {
+            terms,
+            types,
+            from,
+            to,
+        })
+    }
+
+    pub fn matches(&self, date: chrono::NaiveDate, name: &str, dish_type: &str) -> bool {
+        if self.from.is_some_and(|from| date < from) || self.to.is_some_and(|to| date > to) {
+            return false;
+        }
+        if !self.types.is_empty() && !self.types.iter().any(|t| t == dish_type) {
+            return false;
+        }
+
+        let name = fold(name);
+        self.terms.iter().all(|term| name.contains(term.as_str()))
+    }
+
+    /// The matching menu items of a canteen as fetched from upstream.
+    pub fn search_menu(
+        &self,
+        canteen_id: &str,
+        menu_items: &[menu_items::MenuItemsMenuItems],
+    ) -> anyhow::Result<Vec<SearchHit>> {
+        let mut hits = Vec::new();
+        for item in menu_items {
+            let dish = &item.dish;
+            let date = item_date(item)?;
+            let dish_type = dish_type_code(&dish.type_);
+            if !self.matches(date, &dish.name, &dish_type) {
+                continue;
+            }
+
+            hits.push(SearchHit {
+                item_id: item.id.clone(),
+                canteen_id: canteen_id.to_string(),
+                date,
+                dish_id: dish.id.clone(),
+                name: dish.name.clone(),
+                dish_type,
+                student_price: dish.student_price,
+                guest_price: dish.guest_price,
+                upcoming: true,
+            });
+        }
+        Ok(hits)
+    }
+}
+
+/// Combines upcoming and recorded hits, preferring the upcoming version of a menu item, ordered
+/// by date, canteen and name.
+pub fn merge(upcoming: Vec<SearchHit>, recorded: Vec<SearchHit>) -> Vec<SearchHit> {
+    let mut seen = HashSet::new();
+    let mut hits: Vec<SearchHit> = upcoming
+        .into_iter()
+        .chain(recorded)
+        .filter(|hit| seen.insert(hit.item_id.clone()))
+        .collect();
+
+    hits.sort_by(|a, b| (a.date, &a.canteen_id, &a.name).cmp(&(b.date, &b.canteen_id, &b.name)));
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> chrono::NaiveDate {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn fold_expands_umlauts() {
+        assert_eq!(fold("Käsespätzle"), "kaesespaetzle");
+        assert_eq!(fold("KAESESPAETZLE"), "kaesespaetzle");
+        assert_eq!(fold("Grüne Soße"), "gruene sosse");
+        assert_eq!(fold("Crème brûlée"), "creme brulee");
+        assert_eq!(fold("Chili-con-Carne (vegan)"), "chili con carne  vegan ");
+    }
+
+    #[test]
+    fn fold_keeps_other_letters() {
+        assert_eq!(fold("Queue"), "queue");
+        assert_eq!(fold("Sauerkraut"), "sauerkraut");
+        assert_eq!(fold("Poesie"), "poesie");
+    }
+
+    #[test]
+    fn matches_every_word_in_any_spelling() {
+        let search = DishSearch::new("KAESESPAETZLE", &[], None, None).unwrap();
+        assert!(search.matches(
+            date("2026-01-05"),
+            "Käsespätzle mit Röstzwiebeln",
+            "MEATLESS"
+        ));
+
+        let search = DishSearch::new("spätzle röst", &[], None, None).unwrap();
+        assert!(search.matches(
+            date("2026-01-05"),
+            "Käsespätzle mit Röstzwiebeln",
+            "MEATLESS"
+        ));
+        assert!(!search.matches(date("2026-01-05"), "Käsespätzle", "MEATLESS"));
+
+        let search = DishSearch::new("sauerkraut", &[], None, None).unwrap();
+        assert!(!search.matches(date("2026-01-05"), "Saurkraut", "MEATLESS"));
+    }
+
+    #[test]
+    fn matches_types_and_dates() {
+        let search = DishSearch::new(
+            "chili",
+            &["vegan".into()],
+            Some(date("2026-01-05")),
+            Some(date("2026-01-09")),
+        )
+        .unwrap();
+        assert!(search.matches(date("2026-01-05"), "Chili sin Carne", "VEGAN"));
+        assert!(!search.matches(date("2026-01-05"), "Chili con Carne", "BEEF"));
+        assert!(!search.matches(date("2026-01-04"), "Chili sin Carne", "VEGAN"));
+        assert!(!search.matches(date("2026-01-10"), "Chili sin Carne", "VEGAN"));
+    }
+
+    #[test]
+    fn new_rejects_invalid_searches() {
+        assert!(DishSearch::new(" - ", &[], None, None).is_err());
+        assert!(DishSearch::new("chili", &["soup".into()], None, None).is_err());
+        assert!(
+            DishSearch::new(
+                "chili",
+                &[],
+                Some(date("2026-01-09")),
+                Some(date("2026-01-05"))
+            )
+            .is_err()
+        );
+    }
+}
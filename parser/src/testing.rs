@@ -0,0 +1,51 @@
+//! Builders for menu items as upstream returns them, for the unit tests of this and dependent
+//! crates.
+
+use crate::graphql::menu_items::MenuItemsMenuItems;
+
+/// A menu item of a dish on `date`, with everything but the name and prices left empty.
+pub fn menu_item(
+    id: &str,
+    date: &str,
+    dish_id: &str,
+    name: &str,
+    student_price: f64,
+    guest_price: f64,
+) -> MenuItemsMenuItems {
+    let date = date
+        .parse::<chrono::NaiveDate>()
+        .expect("invalid date")
+        .and_time(chrono::NaiveTime::MIN)
+        .and_utc()
+        .timestamp();
+
+    serde_json::from_value(serde_json::json!({
+        "id": id,
+        "date": date,
+        "dish": {
+            "id": dish_id,
+            "type": "MEATLESS",
+            "rating": 0.0,
+            "ratingCount": 0,
+            "dispositionPriority": 0,
+            "name": name,
+            "studentPrice": student_price,
+            "guestPrice": guest_price,
+            "image": null,
+            "allergics": [],
+            "specificAllergics": null,
+            "additionals": [],
+            "mensa_vital": false,
+            "bio": false,
+            "feedback": null,
+            "lastUpdated": 0,
+        },
+        "issuingOffice": {
+            "id": "1",
+            "name": "Studierendenwerk Darmstadt",
+            "canteenId": "1",
+        },
+        "lastUpdated": 0,
+    }))
+    .expect("invalid menu item")
+}
@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing,
+};
+use openmensa_parser_darmstadt::{
+    history::{History, HistoryRecord},
+    search::{self, DishSearch, SearchHit},
+};
+
+use crate::{AppState, feed::v2::FeedKind, upstream};
+
+pub fn router() -> axum::Router<AppState> {
+    axum::Router::new()
+        .route("/search", routing::get(get_search))
+        .route("/dishes/{dish_id}/history", routing::get(get_dish_history))
+        .route("/{identifier}/history", routing::get(get_history))
+}
+
+/// Runs a history query on the blocking thread pool, answering 404 if no history is configured.
+async fn query<T: Send + 'static>(
+    state: &AppState,
+    query: impl FnOnce(&History) -> anyhow::Result<T> + Send + 'static,
+) -> Result<T, Response> {
+    let history = match &state.history {
+        Some(history) => Arc::clone(history),
+        None => return Err((StatusCode::NOT_FOUND, "history is not enabled").into_response()),
+    };
+
+    match tokio::task::spawn_blocking(move || query(&history)).await {
+        Ok(Ok(v)) => Ok(v),
+        Ok(Err(e)) => {
+            tracing::error!("failed to query history: {:?}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+        Err(e) => {
+            tracing::error!("history query panicked: {:?}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct HistoryQuery {
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct HistoryDay {
+    date: chrono::NaiveDate,
+    meals: Vec<HistoryRecord>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CanteenHistory {
+    identifier: String,
+    canteen_id: String,
+    days: Vec<HistoryDay>,
+}
+
+async fn get_history(
+    State(state): State<AppState>,
+    Path(identifier): Path<String>,
+    Query(range): Query<HistoryQuery>,
+) -> Response {
+    let canteen_id = match state.canteens.get().canteen_id(&identifier) {
+        Some(id) => id.clone(),
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+    if let (Some(from), Some(to)) = (range.from, range.to)
+        && to < from
+    {
+        return (StatusCode::BAD_REQUEST, "to must not be before from").into_response();
+    }
+
+    let records = {
+        let canteen_id = canteen_id.clone();
+        match query(&state, move |history| {
+            history.menu(&canteen_id, range.from, range.to)
+        })
+        .await
+        {
+            Ok(v) => v,
+            Err(response) => return response,
+        }
+    };
+
+    // records are ordered by date
+    let mut days: Vec<HistoryDay> = Vec::new();
+    for record in records {
+        match days.last_mut() {
+            Some(day) if day.date == record.date => day.meals.push(record),
+            _ => days.push(HistoryDay {
+                date: record.date,
+                meals: vec![record],
+            }),
+        }
+    }
+
+    axum::Json(CanteenHistory {
+        identifier,
+        canteen_id,
+        days,
+    })
+    .into_response()
+}
+
+async fn get_dish_history(State(state): State<AppState>, Path(dish_id): Path<String>) -> Response {
+    match query(&state, move |history| history.dish(&dish_id)).await {
+        Ok(Some(dish)) => axum::Json(dish).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(response) => response,
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    canteen: Option<String>, // identifier, every canteen with a fetched menu if not set
+    #[serde(rename = "type")]
+    types: Option<String>,
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct SearchResults {
+    query: String,
+    results: Vec<SearchHit>,
+}
+
+/// The upcoming menu of a canteen for a search. A snapshot younger than the full feed cache ttl
+/// is used as is. Searches across every canteen pass `fetch: false` and never query upstream, so
+/// a single request can not fan out into one upstream request per canteen.
+async fn upcoming_menu(
+    state: &AppState,
+    canteen_id: &str,
+    fetch: bool,
+) -> Result<Option<upstream::MenuItems>, Arc<anyhow::Error>> {
+    // the upcoming menu shares its upstream request and snapshot with the full feed
+    let (from, to) = FeedKind::Full.range(chrono::Local::now().date_naive());
+    let snapshot = state.last_known_good.get(canteen_id, from, to);
+    let ttl = state.cache.ttl(FeedKind::Full);
+    let fresh = snapshot
+        .as_ref()
+        .is_some_and(|s| s.age().to_std().is_ok_and(|age| age < ttl));
+    if fresh || !fetch {
+        return Ok(snapshot.map(|s| Arc::new(s.menu_items)));
+    }
+
+    let fetched = upstream::fetch(state, canteen_id, from, to).await?;
+    Ok(Some(fetched.menu_items))
+}
+
+/// Searches the upcoming menus of the registered canteens and, if enabled, the history.
+async fn get_search(State(state): State<AppState>, Query(query): Query<SearchQuery>) -> Response {
+    let types: Vec<String> = query
+        .types
+        .iter()
+        .flat_map(|types| types.split(','))
+        .map(str::to_string)
+        .collect();
+    let search = match DishSearch::new(&query.q, &types, query.from, query.to) {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let canteens = state.canteens.get();
+    let mut canteen_ids: Vec<String> = match &query.canteen {
+        Some(identifier) => match canteens.canteen_id(identifier) {
+            Some(id) => vec![id.clone()],
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => canteens.registered.values().cloned().collect(),
+    };
+    canteen_ids.sort_unstable();
+    canteen_ids.dedup();
+
+    let mut upcoming = Vec::new();
+    for canteen_id in &canteen_ids {
+        let menu_items = match upcoming_menu(&state, canteen_id, query.canteen.is_some()).await {
+            Ok(Some(v)) => v,
+            Ok(None) => continue,
+            Err(e) if state.history.is_some() => {
+                tracing::warn!(
+                    "failed to fetch canteen {} for a search, only searching the history: {:#}",
+                    canteen_id,
+                    e
+                );
+                continue;
+            }
+            Err(e) => {
+                tracing::error!("failed to fetch openmensa data: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        match search.search_menu(canteen_id, &menu_items) {
+            Ok(hits) => upcoming.extend(hits),
+            Err(e) => {
+                tracing::error!("failed to search menu items: {:?}", e);
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        }
+    }
+
+    let recorded = match &state.history {
+        Some(_) => {
+            let search = search.clone();
+            match self::query(&state, move |history| history.search(&search, &canteen_ids)).await {
+                Ok(v) => v,
+                Err(response) => return response,
+            }
+        }
+        None => Vec::new(),
+    };
+
+    axum::Json(SearchResults {
+        query: query.q,
+        results: search::merge(upcoming, recorded),
+    })
+    .into_response()
+}
@@ -0,0 +1,146 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use openmensa_parser_darmstadt::filter::MealFilter;
+use sha2::{Digest, Sha256};
+
+use crate::feed::v2::{FeedKind, Format};
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CacheTtl {
+    pub full: u64,  // seconds, 0 disables caching
+    pub today: u64, // seconds, 0 disables caching
+}
+
+impl Default for CacheTtl {
+    fn default() -> Self {
+        Self {
+            full: 900,
+            today: 300,
+        }
+    }
+}
+
+impl CacheTtl {
+    pub fn seconds(&self, kind: FeedKind) -> u64 {
+        match kind {
+            FeedKind::Today | FeedKind::Next => self.today,
+            FeedKind::Full | FeedKind::Week | FeedKind::Day(_) => self.full,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    pub identifier: String,
+    pub kind: FeedKind,
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub format: Format,
+    pub filter: MealFilter,
+    pub deploy_url: Option<String>, // feed urls in xml responses depend on it
+}
+
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub body: String,
+    pub etag: String,
+    pub last_modified: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl CachedResponse {
+    pub fn new(body: String, last_modified: Option<chrono::DateTime<chrono::Utc>>) -> Self {
+        // a stable digest, so etags survive restarts and rebuilds as long as the body is the same
+        let digest = Sha256::digest(body.as_bytes());
+        let etag = digest[..16]
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+
+        Self {
+            etag: format!("\"{}\"", etag),
+            body,
+            last_modified,
+        }
+    }
+}
+
+pub struct ResponseCache {
+    ttl: CacheTtl,
+    entries: Mutex<HashMap<CacheKey, (Instant, Instant, CachedResponse)>>, // cached at, expiry, response
+}
+
+impl ResponseCache {
+    pub fn new(ttl: CacheTtl) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self, kind: FeedKind) -> Duration {
+        Duration::from_secs(self.ttl.seconds(kind))
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(key)
+            .filter(|(_, expires_at, _)| *expires_at > Instant::now())
+            .map(|(_, _, response)| response.clone())
+    }
+
+    pub fn insert(&self, key: CacheKey, response: CachedResponse, ttl: Duration) {
+        if ttl.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, expires_at, _)| *expires_at > now);
+        entries.insert(key, (now, now + ttl, response));
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Age of the most recently cached response per identifier, ignoring expired ones.
+    pub fn ages(&self) -> HashMap<String, Duration> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+
+        let mut ages: HashMap<String, Duration> = HashMap::new();
+        for (key, (cached_at, expires_at, _)) in entries.iter() {
+            if *expires_at <= now {
+                continue;
+            }
+            let age = now - *cached_at;
+            ages.entry(key.identifier.clone())
+                .and_modify(|v| *v = (*v).min(age))
+                .or_insert(age);
+        }
+
+        ages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn etag_is_a_stable_digest_of_the_body() {
+        let response = CachedResponse::new("<openmensa/>".into(), None);
+        // the first 128 bits of the sha-256 digest of the body
+        assert_eq!(response.etag, "\"a49368af36fc895a8f294e1b469a215f\"");
+        assert_ne!(
+            CachedResponse::new("<openmensa />".into(), None).etag,
+            response.etag
+        );
+    }
+}
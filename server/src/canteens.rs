@@ -0,0 +1,144 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use openmensa_parser_darmstadt::openmensa;
+
+use crate::config::{CanteenMetadata, Config, FeedConfig};
+
+/// The canteens the server answers for, built from the config and optionally extended by
+/// discovered upstream locations.
+#[derive(Debug)]
+pub struct Canteens {
+    pub config: Arc<Config>, // the config these canteens were built from
+    pub registered: HashMap<String, String>, // identifier:canteenId
+    pub feeds: HashMap<String, Vec<FeedConfig>>, // canteenId:feeds
+    pub metadata: HashMap<String, CanteenMetadata>, // canteenId:metadata
+}
+
+impl Canteens {
+    pub fn from_config(config: Arc<Config>) -> Self {
+        let mut canteens = Self {
+            config: config.clone(),
+            registered: HashMap::new(),
+            feeds: HashMap::new(),
+            metadata: HashMap::new(),
+        };
+
+        for (id, canteen) in &config.canteens {
+            for identifier in canteen.identifiers() {
+                canteens.registered.insert(identifier.clone(), id.clone());
+            }
+            canteens.metadata.insert(id.clone(), canteen.metadata());
+            canteens.feeds.insert(
+                id.clone(),
+                canteen.feeds().unwrap_or(&config.feeds).to_vec(),
+            );
+        }
+
+        canteens
+    }
+
+    pub fn deploy_url(&self) -> Option<&str> {
+        self.config.deploy_url.as_deref()
+    }
+
+    pub fn canteen_id(&self, identifier: &str) -> Option<&String> {
+        self.registered.get(identifier)
+    }
+
+    pub fn metadata(&self, canteen_id: &str) -> CanteenMetadata {
+        self.metadata.get(canteen_id).cloned().unwrap_or_default()
+    }
+
+    /// The `<feed>` elements advertised for an identifier, with urls below `deploy_url`.
+    pub fn feeds(
+        &self,
+        canteen_id: &str,
+        identifier: &str,
+        deploy_url: Option<&str>,
+    ) -> Vec<openmensa::Feed> {
+        self.feeds
+            .get(canteen_id)
+            .map(|feeds| {
+                feeds
+                    .iter()
+                    .filter_map(|feed| feed.to_feed(deploy_url, identifier))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The schedule OpenMensa polls the named feed with, if it is advertised. Routes without a
+    /// feed of their own follow the full feed.
+    pub fn schedule(&self, canteen_id: &str, feed_name: &str) -> Option<openmensa::Schedule> {
+        let feeds = self.feeds.get(canteen_id)?;
+        feeds
+            .iter()
+            .find(|feed| feed.name == feed_name)
+            .or_else(|| feeds.iter().find(|feed| feed.name == "full"))
+            .map(|feed| feed.schedule())
+    }
+
+    /// Logs which identifiers were added or removed compared to `previous`.
+    pub fn log_changes(&self, previous: &Canteens, reason: &str) {
+        let mut added: Vec<&String> = self
+            .registered
+            .keys()
+            .filter(|i| !previous.registered.contains_key(*i))
+            .collect();
+        let mut removed: Vec<&String> = previous
+            .registered
+            .keys()
+            .filter(|i| !self.registered.contains_key(*i))
+            .collect();
+        added.sort();
+        removed.sort();
+
+        if !added.is_empty() || !removed.is_empty() {
+            tracing::info!(
+                "{}: added identifiers {:?}, removed identifiers {:?}",
+                reason,
+                added,
+                removed
+            );
+        }
+        if self.deploy_url() != previous.deploy_url() {
+            tracing::info!(
+                "{}: deploy url changed from {:?} to {:?}",
+                reason,
+                previous.deploy_url(),
+                self.deploy_url()
+            );
+        }
+    }
+}
+
+/// Holds the current [`Canteens`], which can be replaced while requests are served.
+pub struct CanteenRegistry {
+    current: RwLock<Arc<Canteens>>,
+    update_lock: tokio::sync::Mutex<()>, // serializes discovery and config reloads
+}
+
+impl CanteenRegistry {
+    pub fn new(canteens: Canteens) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(canteens)),
+            update_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn get(&self) -> Arc<Canteens> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Has to be held while building canteens from the current ones and replacing them.
+    pub async fn lock_updates(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.update_lock.lock().await
+    }
+
+    pub fn replace(&self, canteens: Canteens) -> Arc<Canteens> {
+        std::mem::replace(&mut *self.current.write().unwrap(), Arc::new(canteens))
+    }
+}
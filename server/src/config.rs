@@ -0,0 +1,604 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use openmensa_parser_darmstadt::{graphql::canteen_name, openmensa};
+
+use crate::{
+    cache::CacheTtl, discovery::DiscoveryConfig, fallback::FallbackConfig,
+    forwarded::ForwardedConfig, prefetch::PrefetchConfig, schedule::CronSchedule,
+    webhook::WebhookConfig,
+};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Config {
+    #[serde(default)]
+    pub canteens: HashMap<String, CanteenConfig>, // canteenId:config
+    pub deploy_url: Option<String>,
+    pub bind: Option<String>, // host:port or unix:/path, ignored under socket activation
+    pub socket_mode: Option<String>, // octal permissions of a unix socket, e.g. "660"
+    #[serde(default)]
+    pub cache_ttl: CacheTtl,
+    #[serde(default)]
+    pub last_known_good: FallbackConfig,
+    pub history: Option<PathBuf>, // sqlite database recording every fetched menu item
+    #[serde(default = "default_feeds")]
+    pub feeds: Vec<FeedConfig>,
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    #[serde(default)]
+    pub forwarded: ForwardedConfig, // derive feed urls from proxy headers instead of deployUrl
+    #[serde(default)]
+    pub prefetch: PrefetchConfig,
+    #[serde(default)]
+    pub webhooks: WebhookConfig, // post menu changes to these endpoints
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64, // seconds to wait for in-flight requests on shutdown
+    pub prometheus_prefix: Option<String>, // defaults to $PROMETHEUS_PREFIX or the package name
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+pub enum CanteenConfig {
+    Identifiers(Vec<String>),
+    #[serde(rename_all = "camelCase")]
+    Detailed {
+        identifiers: Vec<String>,
+        feeds: Option<Vec<FeedConfig>>, // replaces the global feeds for this canteen
+        #[serde(default)]
+        metadata: Box<CanteenMetadata>,
+    },
+}
+
+impl CanteenConfig {
+    pub fn identifiers(&self) -> &[String] {
+        match self {
+            CanteenConfig::Identifiers(identifiers) => identifiers,
+            CanteenConfig::Detailed { identifiers, .. } => identifiers,
+        }
+    }
+
+    pub fn feeds(&self) -> Option<&[FeedConfig]> {
+        match self {
+            CanteenConfig::Identifiers(_) => None,
+            CanteenConfig::Detailed { feeds, .. } => feeds.as_deref(),
+        }
+    }
+
+    pub fn metadata(&self) -> CanteenMetadata {
+        match self {
+            CanteenConfig::Identifiers(_) => CanteenMetadata::default(),
+            CanteenConfig::Detailed { metadata, .. } => (**metadata).clone(),
+        }
+    }
+}
+
+/// Canteen details for the OpenMensa metadata feed.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanteenMetadata {
+    pub name: Option<String>,
+    pub address: Option<String>,
+    pub city: Option<String>,
+    pub phone: Option<String>,
+    pub email: Option<String>,
+    pub location: Option<LocationConfig>,
+    pub availability: Option<openmensa::Availability>, // "public" or "restricted"
+    pub times: Option<TimesConfig>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct LocationConfig {
+    pub latitude: f32,
+    pub longitude: f32,
+}
+
+/// Opening times per weekday as `HH:mm-HH:mm`, days that are missing or set to "closed" are
+/// closed.
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+pub struct TimesConfig {
+    pub monday: Option<String>,
+    pub tuesday: Option<String>,
+    pub wednesday: Option<String>,
+    pub thursday: Option<String>,
+    pub friday: Option<String>,
+    pub saturday: Option<String>,
+    pub sunday: Option<String>,
+}
+
+impl TimesConfig {
+    fn to_times(&self) -> openmensa::Times {
+        let weekday = |open: &Option<String>| match open.as_deref().map(str::trim) {
+            None | Some("closed") | Some("") => openmensa::Weekday {
+                open: None,
+                closed: Some(true),
+            },
+            Some(open) => openmensa::Weekday {
+                open: Some(open.to_string()),
+                closed: None,
+            },
+        };
+
+        openmensa::Times {
+            kind: "opening".into(),
+            monday: Some(weekday(&self.monday)),
+            tuesday: Some(weekday(&self.tuesday)),
+            wednesday: Some(weekday(&self.wednesday)),
+            thursday: Some(weekday(&self.thursday)),
+            friday: Some(weekday(&self.friday)),
+            saturday: Some(weekday(&self.saturday)),
+            sunday: Some(weekday(&self.sunday)),
+        }
+    }
+}
+
+impl CanteenMetadata {
+    /// Builds a canteen without days, falling back to the known upstream name of the canteen.
+    pub fn to_canteen(&self, canteen_id: &str, feeds: Vec<openmensa::Feed>) -> openmensa::Canteen {
+        openmensa::Canteen {
+            name: self
+                .name
+                .clone()
+                .or_else(|| canteen_name(canteen_id).map(|v| v.to_string())),
+            address: self.address.clone(),
+            city: self.city.clone(),
+            phone: self.phone.clone(),
+            email: self.email.clone(),
+            location: self.location.as_ref().map(|l| openmensa::Location {
+                latitude: l.latitude,
+                longitude: l.longitude,
+            }),
+            availability: self.availability.clone(),
+            times: self.times.as_ref().map(TimesConfig::to_times),
+            feeds,
+            days: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FeedConfig {
+    pub name: String,
+    pub priority: Option<i32>,
+    pub url: String, // supports {deployUrl} and {identifier} placeholders
+    pub schedule: ScheduleConfig,
+    pub retry: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleConfig {
+    pub hour: String,
+    pub minute: Option<String>,
+    pub day_of_week: Option<String>,
+    pub day_of_month: Option<String>,
+    pub month: Option<String>,
+}
+
+impl FeedConfig {
+    pub fn schedule(&self) -> openmensa::Schedule {
+        openmensa::Schedule {
+            hour: self.schedule.hour.clone(),
+            minute: self.schedule.minute.clone(),
+            day_of_week: self.schedule.day_of_week.clone(),
+            day_of_month: self.schedule.day_of_month.clone(),
+            month: self.schedule.month.clone(),
+            retry: self.retry.clone(),
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        CronSchedule::parse(&self.schedule())
+            .map_err(|e| e.context(format!("feed {}: invalid schedule", self.name)))?;
+        Ok(())
+    }
+
+    /// Builds the `<feed>` element for an identifier, or `None` if the url needs a deploy url but
+    /// none is configured.
+    pub fn to_feed(&self, deploy_url: Option<&str>, identifier: &str) -> Option<openmensa::Feed> {
+        Some(openmensa::Feed {
+            name: self.name.clone(),
+            priority: self.priority,
+            url: expand_url(&self.url, deploy_url, identifier)?,
+            source: None,
+            schedule: Some(self.schedule()),
+        })
+    }
+}
+
+/// Replaces the `{deployUrl}` and `{identifier}` placeholders of a url template, or returns
+/// `None` if it needs a deploy url but none is configured.
+pub fn expand_url(template: &str, deploy_url: Option<&str>, identifier: &str) -> Option<String> {
+    match (template.contains("{deployUrl}"), deploy_url) {
+        (true, None) => None,
+        (_, deploy_url) => Some(
+            template
+                .replace("{deployUrl}", deploy_url.unwrap_or_default())
+                .replace("{identifier}", identifier),
+        ),
+    }
+}
+
+const ENV_PREFIX: &str = "OMPD_";
+
+impl Config {
+    /// Reads the config file, if any, and applies `OMPD_*` environment overrides on top.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let file = match path {
+            Some(path) => read_file(path)?,
+            None => serde_json::Value::Object(Default::default()),
+        };
+
+        // deserializing the file first fills in defaults, so overrides can target their fields
+        let mut value = serde_json::to_value(deserialize(file, "")?)?;
+        apply_env_overrides(&mut value, std::env::vars())?;
+
+        let config = deserialize(value, " after applying environment overrides")?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// A copy without secrets, for printing.
+    pub fn redacted(&self) -> Self {
+        Self {
+            webhooks: self.webhooks.redacted(),
+            ..self.clone()
+        }
+    }
+
+    pub fn socket_mode(&self) -> anyhow::Result<Option<u32>> {
+        self.socket_mode
+            .as_deref()
+            .map(|mode| {
+                u32::from_str_radix(mode, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o777)
+                    .with_context(|| format!("invalid socketMode \"{}\"", mode))
+            })
+            .transpose()
+    }
+
+    /// Checks what deserializing can not, so a broken config is rejected as a whole.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut identifiers: HashMap<&str, &str> = HashMap::new();
+        for (id, canteen) in &self.canteens {
+            for identifier in canteen.identifiers() {
+                if identifier.is_empty() || identifier.contains('/') {
+                    anyhow::bail!("canteen {}: invalid identifier \"{}\"", id, identifier);
+                }
+                if let Some(other) = identifiers.insert(identifier, id)
+                    && other != id
+                {
+                    anyhow::bail!(
+                        "identifier \"{}\" is used by canteens {} and {}",
+                        identifier,
+                        other,
+                        id
+                    );
+                }
+            }
+
+            for feed in canteen.feeds().unwrap_or_default() {
+                feed.validate()
+                    .map_err(|e| e.context(format!("canteen {}", id)))?;
+            }
+        }
+
+        for feed in &self.feeds {
+            feed.validate()?;
+        }
+
+        // the feeds OpenMensa polls, which are prefetched
+        let mut feeds: Vec<&FeedConfig> = self.feeds.iter().collect();
+        for canteen in self.canteens.values() {
+            feeds.extend(canteen.feeds().unwrap_or_default());
+        }
+        self.prefetch.validate(
+            &self.cache_ttl,
+            &feeds,
+            self.deploy_url.as_deref(),
+            self.forwarded.enabled,
+        )?;
+
+        self.socket_mode()?;
+        self.forwarded.validate()?;
+        self.webhooks.validate()?;
+
+        Ok(())
+    }
+}
+
+fn default_feeds() -> Vec<FeedConfig> {
+    vec![
+        FeedConfig {
+            name: "full".into(),
+            priority: Some(1),
+            url: "{deployUrl}/feed/v2/{identifier}/full.xml".into(),
+            schedule: ScheduleConfig {
+                hour: "4".into(),
+                minute: None,
+                day_of_week: Some("*".into()),
+                day_of_month: Some("*".into()),
+                month: None,
+            },
+            retry: Some("60 5 1440".into()),
+        },
+        FeedConfig {
+            name: "today".into(),
+            priority: Some(0),
+            url: "{deployUrl}/feed/v2/{identifier}/today.xml".into(),
+            schedule: ScheduleConfig {
+                hour: "6-16".into(),
+                minute: None,
+                day_of_week: Some("*".into()),
+                day_of_month: Some("*".into()),
+                month: None,
+            },
+            retry: Some("30 1".into()),
+        },
+    ]
+}
+
+fn default_shutdown_timeout() -> u64 {
+    30
+}
+
+fn deserialize(value: serde_json::Value, context: &str) -> anyhow::Result<Config> {
+    serde_path_to_error::deserialize(value).map_err(|e| {
+        anyhow::anyhow!(
+            "invalid config value at \"{}\"{}: {}",
+            e.path(),
+            context,
+            e.inner()
+        )
+    })
+}
+
+/// Parses a json, toml or yaml file, depending on its extension.
+fn read_file(path: &Path) -> anyhow::Result<serde_json::Value> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+
+    let extension = path
+        .extension()
+        .and_then(|v| v.to_str())
+        .unwrap_or_default();
+    let value = match extension {
+        "json" => serde_json::from_str(&content).map_err(anyhow::Error::from),
+        "toml" => toml::from_str(&content).map_err(anyhow::Error::from),
+        // going through yaml values keeps unquoted numeric keys like canteen ids working
+        "yaml" | "yml" => serde_yaml_ng::from_str::<serde_yaml_ng::Value>(&content)
+            .map_err(anyhow::Error::from)
+            .and_then(|v| Ok(serde_json::to_value(v)?)),
+        _ => anyhow::bail!(
+            "unsupported config file {}, expected a .json, .toml, .yaml or .yml file",
+            path.display()
+        ),
+    };
+
+    value.with_context(|| format!("failed to parse config file {}", path.display()))
+}
+
+/// Fields that have no value unless configured and are not strings, as override keys with `*`
+/// for any map key or array index. Unset fields missing here take the raw string.
+const ENV_TYPED_FIELDS: &[&str] = &[
+    "canteens.*",
+    "canteens.*.identifiers",
+    "canteens.*.feeds",
+    "canteens.*.metadata",
+    "canteens.*.metadata.location",
+    "canteens.*.metadata.location.latitude",
+    "canteens.*.metadata.location.longitude",
+    "canteens.*.metadata.times",
+    "feeds.*.priority",
+];
+
+/// Applies overrides like `OMPD_DEPLOY_URL` or `OMPD_CACHE_TTL__FULL`, where `__` separates
+/// nested fields and array indices. Values keep the json type of the value they replace, so
+/// strings stay strings and everything else is parsed as json. Unset fields, like an unset
+/// `bind`, are strings unless listed in [`ENV_TYPED_FIELDS`], so `OMPD_BIND=3000` is not turned
+/// into a number.
+fn apply_env_overrides(
+    value: &mut serde_json::Value,
+    vars: impl Iterator<Item = (String, String)>,
+) -> anyhow::Result<()> {
+    let mut vars = vars
+        .filter_map(|(key, v)| Some((key.strip_prefix(ENV_PREFIX)?.to_string(), v)))
+        .collect::<Vec<_>>();
+    // apply parents first, so nested overrides are not replaced by them
+    vars.sort();
+
+    for (key, raw) in vars {
+        let target = env_target(value, &key)?;
+        let typed = match target {
+            serde_json::Value::String(_) => false,
+            serde_json::Value::Null => env_typed_field(&key),
+            _ => true,
+        };
+
+        // values that are not valid json are kept as string and rejected when deserializing
+        *target = match typed {
+            true => serde_json::from_str(&raw).unwrap_or(serde_json::Value::String(raw)),
+            false => serde_json::Value::String(raw),
+        };
+    }
+
+    Ok(())
+}
+
+fn env_typed_field(key: &str) -> bool {
+    let fields = key.split("__").map(env_field_name).collect::<Vec<_>>();
+    ENV_TYPED_FIELDS.iter().any(|typed| {
+        let typed = typed.split('.').collect::<Vec<_>>();
+        typed.len() == fields.len()
+            && typed
+                .iter()
+                .zip(&fields)
+                .all(|(typed, field)| *typed == "*" || typed == field)
+    })
+}
+
+/// Resolves the value an override key points at, creating missing and unset objects on the way.
+fn env_target<'a>(
+    value: &'a mut serde_json::Value,
+    key: &str,
+) -> anyhow::Result<&'a mut serde_json::Value> {
+    let mut target = value;
+    for segment in key.split("__") {
+        let field = env_field_name(segment);
+        if target.is_null() {
+            *target = serde_json::Value::Object(Default::default());
+        }
+        target = match target {
+            serde_json::Value::Object(map) => map.entry(field).or_insert(serde_json::Value::Null),
+            serde_json::Value::Array(items) => segment
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| items.get_mut(i))
+                .with_context(|| format!("{}{}: no array element {}", ENV_PREFIX, key, segment))?,
+            _ => anyhow::bail!(
+                "{}{}: can not set {} on a value that is not an object",
+                ENV_PREFIX,
+                key,
+                field
+            ),
+        };
+    }
+    Ok(target)
+}
+
+/// Converts an environment variable segment like `DEPLOY_URL` to the field name `deployUrl`.
+fn env_field_name(segment: &str) -> String {
+    let mut field = String::with_capacity(segment.len());
+    let mut upper = false;
+    for c in segment.chars() {
+        match c {
+            '_' if !field.is_empty() => upper = true,
+            c if upper => {
+                field.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => field.extend(c.to_lowercase()),
+        }
+    }
+    field
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(file: serde_json::Value, vars: &[(&str, &str)]) -> anyhow::Result<Config> {
+        let mut value = serde_json::to_value(deserialize(file, "")?)?;
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string()));
+        apply_env_overrides(&mut value, vars)?;
+        deserialize(value, "")
+    }
+
+    #[test]
+    fn numeric_looking_strings_stay_strings() {
+        let config = load(
+            serde_json::json!({}),
+            &[("OMPD_BIND", "3000"), ("OMPD_DEPLOY_URL", "1234")],
+        )
+        .unwrap();
+        assert_eq!(config.bind.as_deref(), Some("3000"));
+        assert_eq!(config.deploy_url.as_deref(), Some("1234"));
+    }
+
+    #[test]
+    fn octal_socket_modes_stay_strings() {
+        let config = load(serde_json::json!({}), &[("OMPD_SOCKET_MODE", "0660")]).unwrap();
+        assert_eq!(config.socket_mode.as_deref(), Some("0660"));
+
+        let config = load(
+            serde_json::json!({ "socketMode": "600" }),
+            &[("OMPD_SOCKET_MODE", "660")],
+        )
+        .unwrap();
+        assert_eq!(config.socket_mode.as_deref(), Some("660"));
+    }
+
+    #[test]
+    fn unset_typed_fields_are_parsed() {
+        let config = load(
+            serde_json::json!({}),
+            &[
+                ("OMPD_CANTEENS__1", "[\"stadtmitte\"]"),
+                ("OMPD_FEEDS__0__PRIORITY", "3"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.canteens["1"].identifiers(), ["stadtmitte"]);
+        assert_eq!(config.feeds[0].priority, Some(3));
+    }
+
+    #[test]
+    fn numbers_are_parsed() {
+        let config = load(
+            serde_json::json!({}),
+            &[
+                ("OMPD_CACHE_TTL__FULL", "60"),
+                ("OMPD_SHUTDOWN_TIMEOUT", "5"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.cache_ttl.full, 60);
+        assert_eq!(config.shutdown_timeout, 5);
+    }
+
+    #[test]
+    fn nested_keys_create_unset_parents() {
+        let config = load(
+            serde_json::json!({ "canteens": { "1": { "identifiers": ["stadtmitte"] } } }),
+            &[
+                ("OMPD_CANTEENS__1__METADATA__LOCATION__LATITUDE", "49.87"),
+                ("OMPD_CANTEENS__1__METADATA__LOCATION__LONGITUDE", "8.65"),
+            ],
+        )
+        .unwrap();
+        let location = config.canteens["1"].metadata().location.unwrap();
+        assert_eq!((location.latitude, location.longitude), (49.87, 8.65));
+
+        let config = load(
+            serde_json::json!({ "canteens": { "1": { "identifiers": ["stadtmitte"] } } }),
+            &[("OMPD_CANTEENS__1__METADATA__TIMES__MONDAY", "11:00-14:00")],
+        )
+        .unwrap();
+        let times = config.canteens["1"].metadata().times.unwrap();
+        assert_eq!(times.monday.as_deref(), Some("11:00-14:00"));
+    }
+
+    #[test]
+    fn redacted_hides_webhook_secrets() {
+        let config = load(
+            serde_json::json!({ "webhooks": { "endpoints": [
+                { "url": "https://example.com/hook", "secret": "hunter2" },
+                { "url": "https://example.com/open" },
+            ] } }),
+            &[],
+        )
+        .unwrap();
+
+        let printed = serde_json::to_string_pretty(&config.redacted()).unwrap();
+        assert!(!printed.contains("hunter2"), "{}", printed);
+        assert!(printed.contains("<redacted>"), "{}", printed);
+        assert_eq!(config.redacted().webhooks.endpoints[1].secret, None);
+    }
+
+    #[test]
+    fn expand_url_needs_a_deploy_url() {
+        let template = "{deployUrl}/feed/v2/{identifier}/full.xml";
+        assert_eq!(
+            expand_url(template, Some("https://example.com"), "stadtmitte").as_deref(),
+            Some("https://example.com/feed/v2/stadtmitte/full.xml")
+        );
+        assert_eq!(expand_url(template, None, "stadtmitte"), None);
+    }
+}
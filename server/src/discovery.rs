@@ -0,0 +1,242 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use openmensa_parser_darmstadt::{
+    graphql::{
+        error_kind,
+        locations::{self, LocationType},
+    },
+    parser::fetch_locations,
+};
+
+use crate::{
+    AppState,
+    canteens::Canteens,
+    config::{CanteenMetadata, Config, LocationConfig},
+    metrics,
+};
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiscoveryConfig {
+    pub enabled: bool,
+    pub interval: u64,      // seconds between refreshes after startup
+    pub types: Vec<String>, // upstream location types to register, e.g. MENSA
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 6 * 60 * 60,
+            types: vec!["MENSA".into(), "BISTRO".into()],
+        }
+    }
+}
+
+fn location_type_code(type_: &LocationType) -> &str {
+    match type_ {
+        LocationType::BISTRO => "BISTRO",
+        LocationType::MENSA => "MENSA",
+        LocationType::BIERGARTEN => "BIERGARTEN",
+        LocationType::KAFFEEBAR => "KAFFEEBAR",
+        LocationType::Other(other) => other,
+    }
+}
+
+/// Derives an identifier from a location name, dropping a leading location type, e.g.
+/// "Mensa Stadtmitte" becomes `stadtmitte` and "Schöffers Campusrestaurant" becomes
+/// `schoeffers-campusrestaurant`. Names without any letters or digits fall back to the location
+/// id.
+pub fn identifier(id: &str, name: &str, type_: &LocationType) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.trim().to_lowercase().chars() {
+        match c {
+            'ä' => slug.push_str("ae"),
+            'ö' => slug.push_str("oe"),
+            'ü' => slug.push_str("ue"),
+            'ß' => slug.push_str("ss"),
+            c if c.is_ascii_alphanumeric() => slug.push(c),
+            _ if !slug.is_empty() && !slug.ends_with('-') => slug.push('-'),
+            _ => {}
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        return id.to_string();
+    }
+
+    let prefix = format!("{}-", location_type_code(type_).to_lowercase());
+    match slug.strip_prefix(&prefix) {
+        Some(rest) if !rest.is_empty() => rest.to_string(),
+        _ => slug.to_string(),
+    }
+}
+
+/// Adds the discovered locations to the configured canteens. Canteens that are configured
+/// explicitly keep their identifiers and feeds, discovered details only fill in missing metadata.
+/// Upstream location ids are the canteen ids used by the menu items query.
+pub fn merge(config: Arc<Config>, locations: &[locations::LocationsLocations]) -> Canteens {
+    let mut canteens = Canteens::from_config(config.clone());
+
+    let wanted = |type_: &LocationType| {
+        let code = location_type_code(type_);
+        config
+            .discovery
+            .types
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(code))
+    };
+
+    for location in locations.iter().filter(|l| wanted(&l.type_)) {
+        let discovered = CanteenMetadata {
+            name: Some(location.name.clone()),
+            location: Some(LocationConfig {
+                latitude: location.coordinates.latitude as f32,
+                longitude: location.coordinates.longitude as f32,
+            }),
+            ..Default::default()
+        };
+
+        if let Some(metadata) = canteens.metadata.get_mut(&location.id) {
+            metadata.name = metadata.name.take().or(discovered.name);
+            metadata.location = metadata.location.take().or(discovered.location);
+            continue;
+        }
+
+        let identifier = identifier(&location.id, &location.name, &location.type_);
+        if let Some(canteen_id) = canteens.registered.get(&identifier) {
+            tracing::warn!(
+                "not registering location {} as \"{}\", the identifier is already used by canteen {}",
+                location.id,
+                identifier,
+                canteen_id
+            );
+            continue;
+        }
+
+        canteens.registered.insert(identifier, location.id.clone());
+        canteens
+            .feeds
+            .insert(location.id.clone(), config.feeds.clone());
+        canteens.metadata.insert(location.id.clone(), discovered);
+    }
+
+    canteens
+}
+
+/// Fetches the upstream locations and builds the canteens for `config` from them.
+pub async fn build(config: Arc<Config>) -> anyhow::Result<Canteens> {
+    let started = Instant::now();
+    let res = fetch_locations().await;
+    metrics::upstream_request("locations", started.elapsed());
+    let locations = res.inspect_err(|e| metrics::upstream_error("locations", error_kind(e)))?;
+
+    Ok(merge(config, &locations))
+}
+
+/// Refreshes the discovered canteens of the current config. On failure the previously
+/// registered canteens are kept.
+pub async fn discover(state: &AppState) -> anyhow::Result<()> {
+    let _guard = state.canteens.lock_updates().await;
+
+    let config = state.canteens.get().config.clone();
+    if !config.discovery.enabled {
+        return Ok(());
+    }
+
+    let canteens = build(config).await?;
+    let previous = state.canteens.replace(canteens);
+    state
+        .canteens
+        .get()
+        .log_changes(&previous, "discovered canteens");
+
+    Ok(())
+}
+
+/// Refreshes the discovered canteens in the interval of the current config.
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            let interval = state.canteens.get().config.discovery.interval.max(60);
+            tokio::time::sleep(Duration::from_secs(interval)).await;
+
+            if let Err(e) = discover(&state).await {
+                tracing::warn!("failed to discover canteens: {:?}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(id: &str, name: &str, type_: &str) -> locations::LocationsLocations {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "coordinates": { "latitude": 49.87, "longitude": 8.65 },
+            "type": type_,
+            "openingHours": "",
+            "description": "",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn identifiers_fold_umlauts() {
+        assert_eq!(
+            identifier("1", "Schöffers Campusrestaurant", &LocationType::MENSA),
+            "schoeffers-campusrestaurant"
+        );
+        assert_eq!(
+            identifier("1", "  Mensa Straße & Grün ", &LocationType::MENSA),
+            "strasse-gruen"
+        );
+        assert_eq!(
+            identifier("1", "Bistro Ärger", &LocationType::BISTRO),
+            "aerger"
+        );
+    }
+
+    #[test]
+    fn identifiers_keep_a_bare_location_type() {
+        assert_eq!(identifier("1", "Mensa", &LocationType::MENSA), "mensa");
+        assert_eq!(
+            identifier("1", "Mensa Lichtwiese", &LocationType::BISTRO),
+            "mensa-lichtwiese"
+        );
+    }
+
+    #[test]
+    fn identifiers_without_a_slug_use_the_id() {
+        assert_eq!(identifier("42", "", &LocationType::MENSA), "42");
+        assert_eq!(identifier("42", " – ", &LocationType::BISTRO), "42");
+    }
+
+    #[test]
+    fn colliding_identifiers_keep_the_first_location() {
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "discovery": { "enabled": true },
+            "canteens": { "1": ["stadtmitte"] },
+        }))
+        .unwrap();
+        let canteens = merge(
+            Arc::new(config),
+            &[
+                location("2", "Bistro Stadtmitte", "BISTRO"),
+                location("3", "Mensa Lichtwiese", "MENSA"),
+                location("4", "Bistro Lichtwiese", "BISTRO"),
+            ],
+        );
+
+        assert_eq!(canteens.canteen_id("stadtmitte").unwrap(), "1");
+        assert_eq!(canteens.canteen_id("lichtwiese").unwrap(), "3");
+        assert!(!canteens.feeds.contains_key("2"));
+        assert!(!canteens.feeds.contains_key("4"));
+    }
+}
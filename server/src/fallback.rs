@@ -0,0 +1,238 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use openmensa_parser_darmstadt::{graphql::menu_items::MenuItemsMenuItems, parser::item_date};
+use serde::{Deserialize, Serialize};
+
+// snapshots that did not change are written at most this often, refreshing their fetch time
+const PERSIST_UNCHANGED_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct FallbackConfig {
+    pub path: Option<PathBuf>, // persist snapshots to this file so they survive restarts
+    pub max_age: u64,          // seconds
+}
+
+impl Default for FallbackConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_age: 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub canteen_id: String,
+    pub from: Option<chrono::NaiveDate>,
+    pub to: Option<chrono::NaiveDate>,
+    pub fetched_at: chrono::DateTime<chrono::Utc>,
+    pub menu_items: Vec<MenuItemsMenuItems>,
+}
+
+impl Snapshot {
+    fn covers(&self, from: Option<chrono::NaiveDate>, to: Option<chrono::NaiveDate>) -> bool {
+        let from_covered = match (self.from, from) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(own), Some(requested)) => own <= requested,
+        };
+        let to_covered = match (self.to, to) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(own), Some(requested)) => own >= requested,
+        };
+
+        from_covered && to_covered
+    }
+
+    pub fn age(&self) -> chrono::Duration {
+        chrono::Utc::now() - self.fetched_at
+    }
+}
+
+type SnapshotKey = (String, Option<chrono::NaiveDate>, Option<chrono::NaiveDate>);
+
+/// Keeps the last successful upstream result per canteen and range, to be served while upstream
+/// is unavailable.
+pub struct LastKnownGood {
+    config: FallbackConfig,
+    snapshots: Mutex<HashMap<SnapshotKey, Snapshot>>,
+    persist_lock: tokio::sync::Mutex<()>,
+    persisted_at: Mutex<Option<Instant>>,
+}
+
+impl LastKnownGood {
+    pub fn new(config: FallbackConfig) -> Self {
+        Self {
+            config,
+            snapshots: Mutex::new(HashMap::new()),
+            persist_lock: tokio::sync::Mutex::new(()),
+            persisted_at: Mutex::new(None),
+        }
+    }
+
+    pub fn load(config: FallbackConfig) -> anyhow::Result<Self> {
+        let store = Self::new(config);
+
+        if let Some(path) = &store.config.path
+            && path.exists()
+        {
+            let file = std::fs::File::open(path)?;
+            let snapshots: Vec<Snapshot> = serde_json::from_reader(std::io::BufReader::new(file))?;
+            tracing::info!(
+                "loaded {} last known good snapshots from {}",
+                snapshots.len(),
+                path.to_string_lossy()
+            );
+
+            let mut map = store.snapshots.lock().unwrap();
+            for snapshot in snapshots {
+                map.insert(
+                    (snapshot.canteen_id.clone(), snapshot.from, snapshot.to),
+                    snapshot,
+                );
+            }
+        }
+
+        Ok(store)
+    }
+
+    fn max_age(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.config.max_age as i64)
+    }
+
+    pub async fn store(
+        &self,
+        canteen_id: &str,
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+        menu_items: &[MenuItemsMenuItems],
+    ) {
+        let changed = {
+            let mut snapshots = self.snapshots.lock().unwrap();
+            let max_age = self.max_age();
+            let count = snapshots.len();
+            snapshots.retain(|_, snapshot| snapshot.age() <= max_age);
+            let expired = snapshots.len() != count;
+            let previous = snapshots.insert(
+                (canteen_id.to_string(), from, to),
+                Snapshot {
+                    canteen_id: canteen_id.to_string(),
+                    from,
+                    to,
+                    fetched_at: chrono::Utc::now(),
+                    menu_items: menu_items.to_vec(),
+                },
+            );
+            expired || previous.is_none_or(|previous| previous.menu_items != menu_items)
+        };
+
+        // refetches mostly return the same menu, skip rewriting the whole file for those
+        let recently_persisted = self
+            .persisted_at
+            .lock()
+            .unwrap()
+            .is_some_and(|at| at.elapsed() < PERSIST_UNCHANGED_INTERVAL);
+        if !changed && recently_persisted {
+            return;
+        }
+
+        if let Err(e) = self.persist().await {
+            tracing::error!("failed to persist last known good snapshots: {:?}", e);
+        }
+    }
+
+    /// Returns the most recent snapshot covering the requested range, restricted to that range,
+    /// unless it exceeds the configured maximum age.
+    pub fn get(
+        &self,
+        canteen_id: &str,
+        from: Option<chrono::NaiveDate>,
+        to: Option<chrono::NaiveDate>,
+    ) -> Option<Snapshot> {
+        let snapshots = self.snapshots.lock().unwrap();
+        let max_age = self.max_age();
+
+        let snapshot = snapshots
+            .values()
+            .filter(|s| s.canteen_id == canteen_id && s.covers(from, to) && s.age() <= max_age)
+            .max_by_key(|s| s.fetched_at)?;
+
+        let menu_items = snapshot
+            .menu_items
+            .iter()
+            .filter(|item| match item_date(item) {
+                Ok(date) => from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to),
+                Err(_) => false,
+            })
+            .cloned()
+            .collect();
+
+        Some(Snapshot {
+            canteen_id: snapshot.canteen_id.clone(),
+            from,
+            to,
+            fetched_at: snapshot.fetched_at,
+            menu_items,
+        })
+    }
+
+    pub async fn persist(&self) -> anyhow::Result<()> {
+        let path = match &self.config.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let _guard = self.persist_lock.lock().await;
+        let content = {
+            let snapshots = self.snapshots.lock().unwrap();
+            serde_json::to_vec(&snapshots.values().collect::<Vec<_>>())?
+        };
+
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, content).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+        *self.persisted_at.lock().unwrap() = Some(Instant::now());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use openmensa_parser_darmstadt::testing::menu_item;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn unchanged_snapshots_are_not_rewritten() {
+        let path = std::env::temp_dir().join(format!("lkg-{}.json", std::process::id()));
+        let store = LastKnownGood::new(FallbackConfig {
+            path: Some(path.clone()),
+            ..Default::default()
+        });
+        let items = vec![menu_item("1", "2026-10-19", "d1", "Seelachs", 4.0, 7.0)];
+
+        store.store("1", None, None, &items).await;
+        assert!(path.exists());
+
+        std::fs::remove_file(&path).unwrap();
+        store.store("1", None, None, &items).await;
+        assert!(!path.exists());
+
+        let changed = vec![menu_item("1", "2026-10-19", "d1", "Seelachs", 4.2, 7.0)];
+        store.store("1", None, None, &changed).await;
+        assert!(path.exists());
+        assert_eq!(store.get("1", None, None).unwrap().menu_items, changed);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
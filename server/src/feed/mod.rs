@@ -1,5 +1,6 @@
 use crate::AppState;
 
+mod negotiate;
 pub mod v2;
 
 pub fn router() -> axum::Router<AppState> {
@@ -0,0 +1,108 @@
+/// Picks the offer the client prefers according to an `Accept` header (RFC 9110, 12.5.1).
+/// Each offer is matched against its most specific media range, ties go to the earlier offer.
+/// Returns `None` if nothing offered is acceptable.
+pub fn preferred<T: Copy>(accept: &str, offers: &[(T, &str)]) -> Option<T> {
+    let ranges = accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let media_type = parts.next()?.to_ascii_lowercase();
+            let (type_, subtype) = media_type.split_once('/')?;
+            let quality = parts
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            Some((type_.to_string(), subtype.to_string(), quality))
+        })
+        .collect::<Vec<_>>();
+
+    let mut best: Option<(T, f32)> = None;
+    for (offer, media_type) in offers {
+        let (type_, subtype) = match media_type.split_once('/') {
+            Some(v) => v,
+            None => continue,
+        };
+
+        // exact matches take precedence over type/* which takes precedence over */*
+        let quality = ranges
+            .iter()
+            .filter_map(|(range_type, range_subtype, quality)| {
+                match (range_type.as_str(), range_subtype.as_str()) {
+                    (t, s) if t == type_ && s == subtype => Some((2, *quality)),
+                    (t, "*") if t == type_ => Some((1, *quality)),
+                    ("*", "*") => Some((0, *quality)),
+                    _ => None,
+                }
+            })
+            .max_by_key(|(specificity, _)| *specificity)
+            .map(|(_, quality)| quality);
+
+        if let Some(quality) = quality
+            && quality > 0.0
+            && best.is_none_or(|(_, best)| quality > best)
+        {
+            best = Some((*offer, quality));
+        }
+    }
+
+    best.map(|(offer, _)| offer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFERS: &[(&str, &str)] = &[
+        ("xml", "application/xml"),
+        ("json", "application/json"),
+        ("html", "text/html"),
+    ];
+
+    #[test]
+    fn highest_quality_wins() {
+        let accept = "application/xml;q=0.5, application/json;q=0.9, text/html;q=0.1";
+        assert_eq!(preferred(accept, OFFERS), Some("json"));
+    }
+
+    #[test]
+    fn ties_go_to_the_earlier_offer() {
+        assert_eq!(
+            preferred("application/json, application/xml", OFFERS),
+            Some("xml")
+        );
+        assert_eq!(preferred("*/*", OFFERS), Some("xml"));
+    }
+
+    #[test]
+    fn most_specific_range_decides() {
+        // text/html is refused even though text/* and */* would accept it
+        let accept = "text/html;q=0, text/*;q=0.8, */*;q=0.1";
+        assert_eq!(preferred(accept, OFFERS), Some("xml"));
+        assert_eq!(
+            preferred("text/*, application/*;q=0.5", OFFERS),
+            Some("html")
+        );
+    }
+
+    #[test]
+    fn media_types_and_parameters_ignore_case() {
+        assert_eq!(
+            preferred("Application/JSON; Q=1, application/xml;q=0.2", OFFERS),
+            Some("json")
+        );
+    }
+
+    #[test]
+    fn unacceptable_offers_give_none() {
+        assert_eq!(preferred("image/png", OFFERS), None);
+        assert_eq!(preferred("application/json;q=0", OFFERS), None);
+        assert_eq!(preferred("*/*;q=0", OFFERS), None);
+    }
+
+    #[test]
+    fn malformed_ranges_are_skipped() {
+        assert_eq!(preferred("garbage, text/html;q=oops", OFFERS), Some("html"));
+    }
+}
@@ -1,132 +1,805 @@
 use axum::{
-    extract::{Path, State},
-    http::{HeaderValue, StatusCode},
+    extract::{Path, Query, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
     routing,
 };
 
+use chrono::Datelike;
 use openmensa_parser_darmstadt::{
+    export,
+    filter::MealFilter,
+    graphql::menu_items::MenuItemsMenuItems,
     openmensa::{self, OpenMensa},
-    parser::fetch_openmensa_for_range,
+    parser::{item_date, menu_items_to_openmensa},
+    render::{render_html, render_ics},
 };
 
-use crate::AppState;
+use super::negotiate;
+use crate::{
+    AppState,
+    cache::{CacheKey, CachedResponse},
+    config::expand_url,
+    forwarded::DeployUrl,
+    metrics, schedule,
+    upstream::{self, Fetched},
+};
+
+const MAX_RANGE_DAYS: i64 = 31;
+const MAX_OFFSET_DAYS: i64 = 90; // how far from today a requested range may start
+const NEXT_LOOKAHEAD_DAYS: u64 = 14;
+// the meta feed route, and its url expanded like the urls of configured feeds
+const META_PATH: &str = "/{identifier}/meta.xml";
+const META_URL: &str = "{deployUrl}/feed/v2/{identifier}/meta.xml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedKind {
+    Full,
+    Today,
+    Week,
+    Day(chrono::NaiveDate),
+    Next, // the first day on or after today that has menu items
+}
+
+impl FeedKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedKind::Full => "full",
+            FeedKind::Today => "today",
+            FeedKind::Week => "week",
+            FeedKind::Day(_) => "day",
+            FeedKind::Next => "next",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "full" => Some(FeedKind::Full),
+            "today" => Some(FeedKind::Today),
+            "week" => Some(FeedKind::Week),
+            "next" => Some(FeedKind::Next),
+            date => chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .ok()
+                .map(FeedKind::Day),
+        }
+    }
+
+    pub fn range(
+        &self,
+        today: chrono::NaiveDate,
+    ) -> (Option<chrono::NaiveDate>, Option<chrono::NaiveDate>) {
+        match self {
+            FeedKind::Full => (Some(today), None),
+            FeedKind::Today => (Some(today), Some(today)),
+            FeedKind::Week => {
+                let monday =
+                    today - chrono::Days::new(today.weekday().num_days_from_monday() as u64);
+                (Some(monday), Some(monday + chrono::Days::new(6)))
+            }
+            FeedKind::Day(date) => (Some(*date), Some(*date)),
+            FeedKind::Next => (
+                Some(today),
+                Some(today + chrono::Days::new(NEXT_LOOKAHEAD_DAYS - 1)),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct RangeQuery {
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+    days: Option<i64>,
+}
+
+impl RangeQuery {
+    /// Applies the query on top of the default range of the requested feed.
+    fn resolve(
+        &self,
+        kind: FeedKind,
+        today: chrono::NaiveDate,
+    ) -> Result<(Option<chrono::NaiveDate>, Option<chrono::NaiveDate>), String> {
+        let (mut from, mut to) = kind.range(today);
+        if self.from.is_some() {
+            from = self.from;
+        }
+        if self.to.is_some() {
+            to = self.to;
+        }
+
+        // checked before any arithmetic, dates near the end of the calendar would overflow it,
+        // this covers the date of a day feed as well
+        if let Some(start) = from
+            && (start - today).num_days().abs() > MAX_OFFSET_DAYS
+        {
+            return Err(format!(
+                "dates must be within {} days of today",
+                MAX_OFFSET_DAYS
+            ));
+        }
+        let add_days = |date: chrono::NaiveDate, days: i64| {
+            date.checked_add_signed(chrono::Duration::days(days))
+                .ok_or_else(|| "date out of range".to_string())
+        };
+
+        if let Some(days) = self.days {
+            if self.to.is_some() {
+                return Err("days and to can not be combined".into());
+            }
+            if !(1..=MAX_RANGE_DAYS).contains(&days) {
+                return Err(format!("days must be between 1 and {}", MAX_RANGE_DAYS));
+            }
+
+            let start = from.unwrap_or(today);
+            from = Some(start);
+            to = Some(add_days(start, days - 1)?);
+        }
+
+        // every open ended range would be a separate upstream query and cache entry
+        if let Some(start) = self.from
+            && to.is_none()
+        {
+            to = Some(add_days(start, MAX_RANGE_DAYS - 1)?);
+        }
+
+        if let (Some(from), Some(to)) = (from, to) {
+            if to < from {
+                return Err("to must not be before from".into());
+            }
+            if (to - from).num_days() >= MAX_RANGE_DAYS {
+                return Err(format!(
+                    "range must not span more than {} days",
+                    MAX_RANGE_DAYS
+                ));
+            }
+        }
+
+        Ok((from, to))
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterQuery {
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    exclude_allergens: Option<String>,
+    exclude_additives: Option<String>,
+}
+
+impl FilterQuery {
+    fn resolve(&self) -> anyhow::Result<MealFilter> {
+        MealFilter::parse(
+            self.type_.as_deref(),
+            self.exclude_allergens.as_deref(),
+            self.exclude_additives.as_deref(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Format {
+    Xml,
+    Csv,
+    Json,
+    Html,
+    Ics,
+}
+
+impl Format {
+    fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "xml" => Some(Format::Xml),
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "html" => Some(Format::Html),
+            "ics" => Some(Format::Ics),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Xml => "xml",
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Html => "html",
+            Format::Ics => "ics",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Format::Xml => "application/xml; charset=utf-8",
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Json => "application/json",
+            Format::Html => "text/html; charset=utf-8",
+            Format::Ics => "text/calendar; charset=utf-8",
+        }
+    }
+
+    /// Picks the format for a request without extension or format parameter. OpenMensa does
+    /// not always send an Accept header, so xml stays the default.
+    fn negotiate(headers: &HeaderMap) -> Option<Self> {
+        const OFFERS: &[(Format, &str)] = &[
+            (Format::Xml, "application/xml"),
+            (Format::Xml, "text/xml"),
+            (Format::Json, "application/json"),
+            (Format::Ics, "text/calendar"),
+            (Format::Html, "text/html"),
+            (Format::Csv, "text/csv"),
+        ];
+
+        match headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) {
+            None => Some(Format::Xml),
+            Some(accept) if accept.trim().is_empty() => Some(Format::Xml),
+            Some(accept) => negotiate::preferred(accept, OFFERS),
+        }
+    }
+}
 
 pub fn router() -> axum::Router<AppState> {
+    // {file} is one of full, today, week, next or a YYYY-MM-DD date, optionally followed by
+    // the format extension
     axum::Router::new()
-        .route("/{identifier}/full.xml", routing::get(get_full))
-        .route("/{identifier}/today.xml", routing::get(get_today))
+        .route("/index.json", routing::get(get_index))
+        .route(META_PATH, routing::get(get_meta))
+        .route("/{identifier}/{file}", routing::get(get_feed))
+}
+
+fn body_to_response(body: String, format: Format) -> Response {
+    let mut response = Response::new(body.into());
+    response.headers_mut().insert(
+        "Content-Type",
+        HeaderValue::from_static(format.content_type()),
+    );
+
+    response
+}
+
+fn feeds(
+    state: &AppState,
+    canteen_id: &str,
+    identifier: &str,
+    deploy_url: Option<&str>,
+) -> Vec<openmensa::Feed> {
+    state
+        .canteens
+        .get()
+        .feeds(canteen_id, identifier, deploy_url)
+}
+
+fn feed_schedule(
+    state: &AppState,
+    canteen_id: &str,
+    kind: FeedKind,
+) -> Option<openmensa::Schedule> {
+    state.canteens.get().schedule(canteen_id, kind.as_str())
 }
 
-fn openmensa_to_response(mensa_data: &OpenMensa) -> Response {
-    match mensa_data.serialize_to_string() {
-        Ok(body) => {
-            let mut response = Response::new(body.into());
-            response
-                .headers_mut()
-                .insert("Content-Type", HeaderValue::from_static("application/xml"));
+fn last_modified(menu_items: &[MenuItemsMenuItems]) -> Option<chrono::DateTime<chrono::Utc>> {
+    menu_items
+        .iter()
+        .map(|item| item.last_updated.max(item.dish.last_updated))
+        .max()
+        .and_then(chrono::DateTime::from_timestamp_secs)
+}
+
+fn http_date(date: chrono::DateTime<chrono::Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
 
-            response
+fn is_not_modified(request_headers: &HeaderMap, cached: &CachedResponse) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since (RFC 9110, 13.1.3)
+    if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|v| {
+            v.split(',')
+                .map(|tag| tag.trim().trim_start_matches("W/"))
+                .any(|tag| tag == "*" || tag == cached.etag)
+        });
+    }
+
+    match (
+        request_headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| chrono::DateTime::parse_from_rfc2822(v).ok()),
+        cached.last_modified,
+    ) {
+        (Some(since), Some(last_modified)) => last_modified <= since,
+        _ => false,
+    }
+}
+
+fn respond(
+    request_headers: &HeaderMap,
+    cached: CachedResponse,
+    schedule: Option<openmensa::Schedule>,
+    format: Format,
+    stale_age: Option<chrono::Duration>,
+) -> Response {
+    let mut response = match is_not_modified(request_headers, &cached) {
+        true => StatusCode::NOT_MODIFIED.into_response(),
+        false => body_to_response(cached.body, format),
+    };
+
+    let headers = response.headers_mut();
+    if let Ok(etag) = HeaderValue::from_str(&cached.etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Some(last_modified) = cached.last_modified
+        && let Ok(last_modified) = HeaderValue::from_str(&http_date(last_modified))
+    {
+        headers.insert(header::LAST_MODIFIED, last_modified);
+    }
+
+    match stale_age {
+        Some(age) => {
+            headers.insert(
+                header::WARNING,
+                HeaderValue::from_static("110 - \"Response is Stale\""),
+            );
+            headers.insert(header::AGE, HeaderValue::from(age.num_seconds().max(0)));
+            headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
         }
-        Err(e) => {
-            tracing::error!("failed to serialize openmensa data: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        None => {
+            // responses stay fresh until OpenMensa is scheduled to poll the feed again
+            let now = chrono::Local::now().naive_local();
+            if let Some(max_age) = schedule
+                .as_ref()
+                .and_then(|schedule| schedule::seconds_until_next(schedule, now))
+                && let Ok(cache_control) =
+                    HeaderValue::from_str(&format!("public, max-age={}", max_age))
+            {
+                headers.insert(header::CACHE_CONTROL, cache_control);
+            }
+        }
+    }
+
+    response
+}
+
+fn render(
+    state: &AppState,
+    key: &CacheKey,
+    canteen_id: &str,
+    menu_items: &[MenuItemsMenuItems],
+) -> anyhow::Result<String> {
+    let identifier = key.identifier.as_str();
+    match key.format {
+        Format::Xml => {
+            let mut data = menu_items_to_openmensa(menu_items)?;
+            data.canteen.feeds.extend(feeds(
+                state,
+                canteen_id,
+                identifier,
+                key.deploy_url.as_deref(),
+            ));
+            data.serialize_to_string()
         }
+        Format::Csv => export::to_csv(&export::meal_records(canteen_id, menu_items)?),
+        Format::Json => Ok(serde_json::to_string(&export::meal_records(
+            canteen_id, menu_items,
+        )?)?),
+        Format::Html => Ok(render_html(
+            identifier,
+            &menu_items_to_openmensa(menu_items)?.canteen.days,
+        )),
+        Format::Ics => Ok(render_ics(
+            identifier,
+            &menu_items_to_openmensa(menu_items)?.canteen.days,
+            last_modified(menu_items).unwrap_or_default(),
+        )),
     }
 }
 
-async fn get_full(State(state): State<AppState>, Path(identifier): Path<String>) -> Response {
-    let canteen_id = match state.registered_canteens.get(&identifier) {
+/// The menu items of the first day on or after `from` that has any.
+fn first_day(
+    menu_items: &[MenuItemsMenuItems],
+    from: Option<chrono::NaiveDate>,
+) -> Vec<MenuItemsMenuItems> {
+    let first = menu_items
+        .iter()
+        .filter_map(|item| item_date(item).ok())
+        .filter(|date| from.is_none_or(|from| *date >= from))
+        .min();
+    if first.is_none() {
+        return Vec::new();
+    }
+
+    menu_items
+        .iter()
+        .filter(|item| item_date(item).ok() == first)
+        .cloned()
+        .collect()
+}
+
+async fn feed_response(state: AppState, headers: HeaderMap, key: CacheKey) -> Response {
+    let canteens = state.canteens.get();
+    let canteen_id = match canteens.canteen_id(&key.identifier) {
         Some(id) => id,
         None => return StatusCode::NOT_FOUND.into_response(),
     };
+    let (kind, format) = (key.kind, key.format);
 
-    let today = chrono::Local::now().date_naive();
-    match fetch_openmensa_for_range(canteen_id.clone(), Some(today), None).await {
-        Ok(mut data) => {
-            if let Some(deploy_url) = state.deploy_url {
-                data.canteen.feeds.push(openmensa::Feed {
-                    name: "full".into(),
-                    priority: Some(1),
-                    url: format!("{}/feed/v2/{}/full.xml", deploy_url, identifier),
-                    source: None,
-                    schedule: Some(openmensa::Schedule {
-                        day_of_month: Some("*".into()),
-                        day_of_week: Some("*".into()),
-                        hour: "4".into(),
-                        retry: Some("60 5 1440".into()),
-                        minute: None,
-                        month: None,
-                    }),
-                });
-                data.canteen.feeds.push(openmensa::Feed {
-                    name: "today".into(),
-                    priority: Some(0),
-                    url: format!("{}/feed/v2/{}/today.xml", deploy_url, identifier),
-                    source: None,
-                    schedule: Some(openmensa::Schedule {
-                        day_of_month: Some("*".into()),
-                        day_of_week: Some("*".into()),
-                        hour: "6-16".into(),
-                        retry: Some("30 1".into()),
-                        minute: None,
-                        month: None,
-                    }),
-                });
-            }
-            openmensa_to_response(&data)
-        }
+    if let Some(cached) = state.cache.get(&key) {
+        metrics::cache_hit(kind.as_str(), format.extension());
+        return respond(
+            &headers,
+            cached,
+            feed_schedule(&state, canteen_id, kind),
+            format,
+            None,
+        );
+    }
+    metrics::cache_miss(kind.as_str(), format.extension());
+
+    let fetched = match upstream::fetch(&state, canteen_id, key.from, key.to).await {
+        Ok(v) => v,
         Err(e) => {
             tracing::error!("failed to fetch openmensa data: {:?}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    match render_fetched(&state, key, canteen_id, &fetched) {
+        Ok(response) => respond(
+            &headers,
+            response,
+            feed_schedule(&state, canteen_id, kind),
+            format,
+            fetched.stale_age,
+        ),
+        Err(e) => {
+            tracing::error!("failed to render {} feed: {:?}", format.extension(), e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
 
-async fn get_today(State(state): State<AppState>, Path(identifier): Path<String>) -> Response {
-    let canteen_id = match state.registered_canteens.get(&identifier) {
-        Some(id) => id,
+/// Filters and renders fetched menu items for `key` and caches the response, unless it was
+/// served from the last known good snapshot.
+pub fn render_fetched(
+    state: &AppState,
+    key: CacheKey,
+    canteen_id: &str,
+    fetched: &Fetched,
+) -> anyhow::Result<CachedResponse> {
+    let filtered;
+    let menu_items = match key.filter.is_empty() {
+        true => fetched.menu_items.as_slice(),
+        false => {
+            filtered = key.filter.apply(&fetched.menu_items);
+            &filtered
+        }
+    };
+    let next_day;
+    let menu_items = match key.kind {
+        FeedKind::Next => {
+            next_day = first_day(menu_items, key.from);
+            &next_day
+        }
+        _ => menu_items,
+    };
+
+    let body = render(state, &key, canteen_id, menu_items)?;
+    let response = CachedResponse::new(body, last_modified(menu_items));
+    if fetched.stale_age.is_none() {
+        let ttl = state.cache.ttl(key.kind);
+        state.cache.insert(key, response.clone(), ttl);
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+pub struct FormatQuery {
+    format: Option<String>,
+}
+
+async fn get_feed(
+    State(state): State<AppState>,
+    DeployUrl(deploy_url): DeployUrl,
+    headers: HeaderMap,
+    Path((identifier, file)): Path<(String, String)>,
+    Query(query): Query<RangeQuery>,
+    Query(filter): Query<FilterQuery>,
+    Query(format_query): Query<FormatQuery>,
+) -> Response {
+    let (kind, extension) = match file.rsplit_once('.') {
+        Some((name, extension)) => match Format::from_extension(extension) {
+            Some(format) => (FeedKind::parse(name), Some(format)),
+            None => return StatusCode::NOT_FOUND.into_response(),
+        },
+        None => (FeedKind::parse(&file), None),
+    };
+    let kind = match kind {
+        Some(v) => v,
         None => return StatusCode::NOT_FOUND.into_response(),
     };
 
-    let today = chrono::Local::now().date_naive();
-    match fetch_openmensa_for_range(canteen_id.clone(), Some(today), Some(today)).await {
-        Ok(mut data) => {
-            if let Some(deploy_url) = state.deploy_url {
-                data.canteen.feeds.push(openmensa::Feed {
-                    name: "full".into(),
-                    priority: Some(1),
-                    url: format!("{}/feed/v2/{}/full.xml", deploy_url, identifier),
-                    source: None,
-                    schedule: Some(openmensa::Schedule {
-                        day_of_month: Some("*".into()),
-                        day_of_week: Some("*".into()),
-                        hour: "8".into(),
-                        retry: Some("60 5 1440".into()),
-                        minute: None,
-                        month: None,
-                    }),
-                });
-                data.canteen.feeds.push(openmensa::Feed {
-                    name: "today".into(),
-                    priority: Some(0),
-                    url: format!("{}/feed/v2/{}/today.xml", deploy_url, identifier),
-                    source: None,
-                    schedule: Some(openmensa::Schedule {
-                        day_of_month: Some("*".into()),
-                        day_of_week: Some("*".into()),
-                        hour: "6-16".into(),
-                        retry: Some("30 1".into()),
-                        minute: None,
-                        month: None,
-                    }),
-                });
+    // ?format= overrides the extension, which overrides the Accept header
+    let requested = match format_query.format.as_deref() {
+        Some(format) => match Format::from_extension(format) {
+            Some(v) => Some(v),
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("unknown format \"{}\"", format),
+                )
+                    .into_response();
             }
-            openmensa_to_response(&data)
+        },
+        None => extension,
+    };
+    let negotiated = requested.is_none();
+    let format = match requested.or_else(|| Format::negotiate(&headers)) {
+        Some(v) => v,
+        None => {
+            return (
+                StatusCode::NOT_ACCEPTABLE,
+                [(header::VARY, HeaderValue::from_static("accept"))],
+            )
+                .into_response();
         }
+    };
+
+    let today = chrono::Local::now().date_naive();
+    let (from, to) = match query.resolve(kind, today) {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+    let filter = match filter.resolve() {
+        Ok(v) => v,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let key = CacheKey {
+        identifier,
+        kind,
+        from,
+        to,
+        format,
+        filter,
+        deploy_url,
+    };
+    let mut response = feed_response(state, headers, key).await;
+    if negotiated {
+        response
+            .headers_mut()
+            .insert(header::VARY, HeaderValue::from_static("accept"));
+    }
+
+    response
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Index {
+    canteens: Vec<IndexEntry>,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct IndexEntry {
+    identifier: String,
+    canteen_id: String,
+    name: Option<String>,
+    meta_url: Option<String>,
+    feeds: Vec<IndexFeed>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct IndexFeed {
+    name: String,
+    url: String,
+}
+
+async fn get_index(State(state): State<AppState>, DeployUrl(deploy_url): DeployUrl) -> Response {
+    let registry = state.canteens.get();
+    let mut canteens = registry
+        .registered
+        .iter()
+        .map(|(identifier, canteen_id)| IndexEntry {
+            identifier: identifier.clone(),
+            canteen_id: canteen_id.clone(),
+            name: registry
+                .metadata(canteen_id)
+                .to_canteen(canteen_id, Vec::new())
+                .name,
+            meta_url: expand_url(META_URL, deploy_url.as_deref(), identifier),
+            feeds: feeds(&state, canteen_id, identifier, deploy_url.as_deref())
+                .into_iter()
+                .map(|feed| IndexFeed {
+                    name: feed.name,
+                    url: feed.url,
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    canteens.sort_by(|a, b| a.identifier.cmp(&b.identifier));
+
+    axum::Json(Index { canteens }).into_response()
+}
+
+/// OpenMensa document with the canteen metadata and feeds but without days, as used when
+/// registering the parser on openmensa.org.
+async fn get_meta(
+    State(state): State<AppState>,
+    DeployUrl(deploy_url): DeployUrl,
+    Path(identifier): Path<String>,
+) -> Response {
+    let canteens = state.canteens.get();
+    let canteen_id = match canteens.canteen_id(&identifier) {
+        Some(id) => id,
+        None => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let data = OpenMensa::new(canteens.metadata(canteen_id).to_canteen(
+        canteen_id,
+        feeds(&state, canteen_id, &identifier, deploy_url.as_deref()),
+    ));
+
+    match data.serialize_to_string() {
+        Ok(body) => body_to_response(body, Format::Xml),
         Err(e) => {
-            tracing::error!("failed to fetch openmensa data: {:?}", e);
+            tracing::error!("failed to render meta feed: {:?}", e);
             StatusCode::INTERNAL_SERVER_ERROR.into_response()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use openmensa_parser_darmstadt::testing::menu_item;
+
+    use super::*;
+
+    fn date(s: &str) -> chrono::NaiveDate {
+        s.parse().unwrap()
+    }
+
+    fn resolve(
+        from: Option<&str>,
+        to: Option<&str>,
+        days: Option<i64>,
+    ) -> Result<(Option<chrono::NaiveDate>, Option<chrono::NaiveDate>), String> {
+        let query = RangeQuery {
+            from: from.map(date),
+            to: to.map(date),
+            days,
+        };
+        query.resolve(FeedKind::Full, date("2026-10-19"))
+    }
+
+    #[test]
+    fn meta_url_points_at_the_meta_route() {
+        assert!(META_URL.ends_with(META_PATH));
+        assert_eq!(
+            expand_url(META_URL, Some("https://example.com"), "stadtmitte").as_deref(),
+            Some("https://example.com/feed/v2/stadtmitte/meta.xml")
+        );
+        assert_eq!(expand_url(META_URL, None, "stadtmitte"), None);
+    }
+
+    #[test]
+    fn full_feed_without_query_stays_open_ended() {
+        assert_eq!(
+            resolve(None, None, None),
+            Ok((Some(date("2026-10-19")), None))
+        );
+    }
+
+    #[test]
+    fn open_ended_ranges_are_clamped() {
+        assert_eq!(
+            resolve(Some("2026-10-01"), None, None),
+            Ok((Some(date("2026-10-01")), Some(date("2026-10-31"))))
+        );
+    }
+
+    #[test]
+    fn distant_from_is_rejected() {
+        assert!(resolve(Some("2000-01-01"), None, None).is_err());
+        assert!(resolve(Some("2027-10-01"), Some("2027-10-02"), None).is_err());
+        assert!(resolve(Some("2026-07-21"), None, None).is_ok());
+    }
+
+    #[test]
+    fn dates_at_the_end_of_the_calendar_are_rejected() {
+        let query = RangeQuery {
+            from: Some(chrono::NaiveDate::MAX),
+            to: None,
+            days: Some(2),
+        };
+        assert!(query.resolve(FeedKind::Full, date("2026-10-19")).is_err());
+
+        let query = RangeQuery {
+            from: None,
+            to: Some(chrono::NaiveDate::MAX),
+            days: None,
+        };
+        assert!(query.resolve(FeedKind::Full, date("2026-10-19")).is_err());
+    }
+
+    #[test]
+    fn distant_day_feeds_are_rejected() {
+        let query = RangeQuery::default();
+        let today = date("2026-10-19");
+        assert!(
+            query
+                .resolve(FeedKind::Day(chrono::NaiveDate::MAX), today)
+                .is_err()
+        );
+        assert!(
+            query
+                .resolve(FeedKind::Day(date("2027-10-19")), today)
+                .is_err()
+        );
+        assert_eq!(
+            query.resolve(FeedKind::Day(date("2026-10-26")), today),
+            Ok((Some(date("2026-10-26")), Some(date("2026-10-26"))))
+        );
+
+        let query = RangeQuery {
+            days: Some(2),
+            ..Default::default()
+        };
+        assert!(
+            query
+                .resolve(FeedKind::Day(chrono::NaiveDate::MAX), today)
+                .is_err()
+        );
+    }
+
+    fn item_dates(menu_items: &[MenuItemsMenuItems]) -> Vec<String> {
+        menu_items
+            .iter()
+            .map(|item| item_date(item).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn first_day_skips_a_closed_today() {
+        let menu_items = [
+            menu_item("1", "2026-10-21", "10", "Chili", 3.5, 5.5),
+            menu_item("2", "2026-10-20", "11", "Gulasch", 4.0, 6.0),
+            menu_item("3", "2026-10-20", "12", "Pasta", 3.0, 5.0),
+        ];
+        assert_eq!(
+            item_dates(&first_day(&menu_items, Some(date("2026-10-19")))),
+            ["2026-10-20", "2026-10-20"]
+        );
+    }
+
+    #[test]
+    fn first_day_of_an_empty_menu_is_empty() {
+        assert!(first_day(&[], Some(date("2026-10-19"))).is_empty());
+    }
+
+    #[test]
+    fn first_day_ignores_past_menus() {
+        let menu_items = [
+            menu_item("1", "2026-10-16", "10", "Chili", 3.5, 5.5),
+            menu_item("2", "2026-10-18", "11", "Gulasch", 4.0, 6.0),
+        ];
+        assert!(first_day(&menu_items, Some(date("2026-10-19"))).is_empty());
+
+        let menu_items = [
+            menu_items[0].clone(),
+            menu_item("3", "2026-10-19", "12", "Pasta", 3.0, 5.0),
+        ];
+        assert_eq!(
+            item_dates(&first_day(&menu_items, Some(date("2026-10-19")))),
+            ["2026-10-19"]
+        );
+    }
+
+    #[test]
+    fn explicit_ranges_are_limited() {
+        assert_eq!(
+            resolve(Some("2026-10-19"), None, Some(7)),
+            Ok((Some(date("2026-10-19")), Some(date("2026-10-25"))))
+        );
+        assert!(resolve(Some("2026-10-01"), Some("2026-11-30"), None).is_err());
+        assert!(resolve(Some("2026-10-20"), Some("2026-10-19"), None).is_err());
+    }
+}
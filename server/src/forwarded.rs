@@ -0,0 +1,478 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use anyhow::Context;
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, connect_info::Connected},
+    http::{HeaderMap, header, request::Parts},
+    serve::IncomingStream,
+};
+use tokio::net::{TcpListener, UnixListener};
+
+use crate::AppState;
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ForwardedConfig {
+    pub enabled: bool,
+    pub trusted_proxies: Vec<String>, // ip addresses, cidr ranges or "unix" for unix socket peers
+}
+
+impl Default for ForwardedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            trusted_proxies: vec!["127.0.0.1".into(), "::1".into(), "unix".into()],
+        }
+    }
+}
+
+impl ForwardedConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for proxy in &self.trusted_proxies {
+            TrustedProxy::parse(proxy)?;
+        }
+        Ok(())
+    }
+
+    fn trusted_proxies(&self) -> impl Iterator<Item = TrustedProxy> {
+        self.trusted_proxies
+            .iter()
+            .filter_map(|proxy| TrustedProxy::parse(proxy).ok())
+    }
+
+    fn is_trusted(&self, peer: &PeerAddr) -> bool {
+        match peer {
+            PeerAddr::Tcp(addr) => self.is_trusted_ip(addr.ip()),
+            PeerAddr::Unix => self
+                .trusted_proxies()
+                .any(|proxy| matches!(proxy, TrustedProxy::Unix)),
+        }
+    }
+
+    fn is_trusted_ip(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies().any(|proxy| proxy.contains(ip))
+    }
+}
+
+enum TrustedProxy {
+    Unix,
+    Network(IpAddr, u8), // address, prefix length
+}
+
+impl TrustedProxy {
+    fn parse(proxy: &str) -> anyhow::Result<Self> {
+        if proxy == "unix" {
+            return Ok(Self::Unix);
+        }
+
+        let invalid = || format!("invalid trusted proxy \"{}\"", proxy);
+        let (addr, prefix) = match proxy.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (proxy, None),
+        };
+        let addr = addr.parse::<IpAddr>().with_context(invalid)?;
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .with_context(invalid)?,
+            None => max,
+        };
+
+        Ok(Self::Network(addr, prefix))
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        let (network, prefix) = match self {
+            Self::Unix => return false,
+            Self::Network(network, prefix) => (network, *prefix as u32),
+        };
+
+        // peers of dual stack sockets show up as ipv4-mapped ipv6 addresses
+        match (network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The peer of a connection, available to handlers as `ConnectInfo<PeerAddr>`.
+#[derive(Debug, Clone, Copy)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    Unix,
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self::Tcp(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, UnixListener>> for PeerAddr {
+    fn connect_info(_stream: IncomingStream<'_, UnixListener>) -> Self {
+        Self::Unix
+    }
+}
+
+/// The url clients reach the server under. Built from the forwarded headers of a trusted proxy
+/// if enabled, otherwise the configured deploy url.
+pub struct DeployUrl(pub Option<String>);
+
+impl FromRequestParts<AppState> for DeployUrl {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Infallible> {
+        let canteens = state.canteens.get();
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<PeerAddr>>()
+            .map(|ConnectInfo(peer)| peer);
+        let forwarded = forwarded_url(&canteens.config.forwarded, peer, &parts.headers);
+
+        Ok(Self(
+            forwarded.or_else(|| canteens.deploy_url().map(str::to_string)),
+        ))
+    }
+}
+
+/// The url from the forwarded headers, if enabled and sent by a trusted proxy.
+fn forwarded_url(
+    config: &ForwardedConfig,
+    peer: Option<&PeerAddr>,
+    headers: &HeaderMap,
+) -> Option<String> {
+    let trusted = config.enabled && peer.is_some_and(|peer| config.is_trusted(peer));
+    match trusted {
+        true => from_headers(headers, config),
+        false => None,
+    }
+}
+
+/// Parses the elements of the `Forwarded` headers (RFC 7239) into their parameters, with
+/// lowercased names.
+fn forwarded_elements(headers: &HeaderMap) -> Vec<Vec<(String, String)>> {
+    headers
+        .get_all(header::FORWARDED)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(name, value)| {
+                    (
+                        name.trim().to_ascii_lowercase(),
+                        value.trim().trim_matches('"').to_string(),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn param<'a>(element: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    element
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Parses a `for=` node like `192.0.2.60`, `192.0.2.60:4711` or `[2001:db8::1]:4711`.
+fn node_ip(node: &str) -> Option<IpAddr> {
+    let host = match node.strip_prefix('[') {
+        Some(rest) => rest.split_once(']')?.0,
+        None => node.split_once(':').map_or(node, |(host, _)| host),
+    };
+    host.parse().ok()
+}
+
+/// The last value of a header that may have been appended to by several proxies, as set by the
+/// proxy closest to the server.
+fn last_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .rfind(|v| !v.is_empty())
+}
+
+fn from_headers(headers: &HeaderMap, config: &ForwardedConfig) -> Option<String> {
+    let elements = forwarded_elements(headers);
+    let (host, proto) = match elements.is_empty() {
+        true => (
+            last_value(headers, "x-forwarded-host").map(str::to_string),
+            last_value(headers, "x-forwarded-proto").map(str::to_string),
+        ),
+        false => {
+            // walk back from the element added by our peer as long as the proxies are trusted
+            let mut selected = &elements[elements.len() - 1];
+            for element in elements.iter().rev() {
+                selected = element;
+                if !param(element, "for")
+                    .and_then(node_ip)
+                    .is_some_and(|ip| config.is_trusted_ip(ip))
+                {
+                    break;
+                }
+            }
+            (
+                param(selected, "host").map(str::to_string),
+                param(selected, "proto").map(str::to_string),
+            )
+        }
+    };
+    let prefix = last_value(headers, "x-forwarded-prefix");
+
+    if host.is_none() && proto.is_none() && prefix.is_none() {
+        return None;
+    }
+
+    let host = host.or_else(|| {
+        headers
+            .get(header::HOST)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    })?;
+    let proto = proto.unwrap_or("http".into()).to_ascii_lowercase();
+    let prefix = prefix.unwrap_or_default().trim_end_matches('/');
+
+    let valid_host = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".-_:[]".contains(c));
+    let valid_proto = proto == "http" || proto == "https";
+    let valid_prefix = (prefix.is_empty() || prefix.starts_with('/'))
+        && prefix
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-._~/%".contains(c));
+    if !valid_host || !valid_proto || !valid_prefix {
+        tracing::debug!(
+            "ignoring invalid forwarded values, host: {:?}, proto: {:?}, prefix: {:?}",
+            host,
+            proto,
+            prefix
+        );
+        return None;
+    }
+
+    Some(format!("{}://{}{}", proto, host, prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded_config(trusted_proxies: &[&str]) -> ForwardedConfig {
+        ForwardedConfig {
+            enabled: true,
+            trusted_proxies: trusted_proxies.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    fn header_map(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn tcp(ip: &str) -> PeerAddr {
+        PeerAddr::Tcp(SocketAddr::new(ip.parse().unwrap(), 4711))
+    }
+
+    #[test]
+    fn trusted_proxies_match_cidr_ranges() {
+        let config = forwarded_config(&["10.0.0.0/8", "2001:db8::/32", "192.0.2.1"]);
+        assert!(config.is_trusted(&tcp("10.1.2.3")));
+        assert!(config.is_trusted(&tcp("::ffff:10.1.2.3")));
+        assert!(config.is_trusted(&tcp("2001:db8::1")));
+        assert!(config.is_trusted(&tcp("192.0.2.1")));
+        assert!(!config.is_trusted(&tcp("192.0.2.2")));
+        assert!(!config.is_trusted(&tcp("11.0.0.1")));
+        assert!(!config.is_trusted(&tcp("2001:db9::1")));
+        assert!(!config.is_trusted(&PeerAddr::Unix));
+
+        let config = forwarded_config(&["0.0.0.0/0", "unix"]);
+        assert!(config.is_trusted(&tcp("203.0.113.9")));
+        assert!(!config.is_trusted(&tcp("::1")));
+        assert!(config.is_trusted(&PeerAddr::Unix));
+    }
+
+    #[test]
+    fn invalid_trusted_proxies_are_rejected() {
+        for proxy in [
+            "localhost",
+            "10.0.0.0/33",
+            "::1/129",
+            "10.0.0.0/",
+            "unix:/run",
+        ] {
+            assert!(forwarded_config(&[proxy]).validate().is_err(), "{}", proxy);
+        }
+        assert!(
+            forwarded_config(&["10.0.0.0/8", "::/0", "unix"])
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn last_value_is_set_by_the_closest_proxy() {
+        let headers = header_map(&[
+            ("x-forwarded-host", "a.example.com, b.example.com"),
+            ("x-forwarded-host", "c.example.com,"),
+        ]);
+        assert_eq!(
+            last_value(&headers, "x-forwarded-host"),
+            Some("c.example.com")
+        );
+        assert_eq!(last_value(&headers, "x-forwarded-proto"), None);
+    }
+
+    #[test]
+    fn x_forwarded_headers_build_the_url() {
+        let headers = header_map(&[
+            ("host", "internal:3000"),
+            ("x-forwarded-host", "mensa.example.com"),
+            ("x-forwarded-proto", "HTTPS"),
+            ("x-forwarded-prefix", "/openmensa/"),
+        ]);
+        assert_eq!(
+            forwarded_url(
+                &forwarded_config(&["127.0.0.1"]),
+                Some(&tcp("127.0.0.1")),
+                &headers
+            )
+            .as_deref(),
+            Some("https://mensa.example.com/openmensa")
+        );
+
+        let headers = header_map(&[("host", "internal:3000"), ("x-forwarded-proto", "https")]);
+        assert_eq!(
+            from_headers(&headers, &forwarded_config(&[])).as_deref(),
+            Some("https://internal:3000")
+        );
+        assert_eq!(
+            from_headers(&HeaderMap::new(), &forwarded_config(&[])),
+            None
+        );
+    }
+
+    #[test]
+    fn forwarded_chain_stops_at_the_first_untrusted_hop() {
+        let config = forwarded_config(&["10.0.0.0/8"]);
+        // the client at 203.0.113.7 sent the first element itself, the outer proxy at 10.0.0.1
+        // added the second and the proxy in front of the server the third
+        let headers = header_map(&[
+            (
+                "forwarded",
+                "for=10.9.9.9;host=spoofed.example.com;proto=http",
+            ),
+            (
+                "forwarded",
+                "for=203.0.113.7;host=mensa.example.com;proto=https, for=10.0.0.1;host=outer",
+            ),
+        ]);
+        assert_eq!(
+            from_headers(&headers, &config).as_deref(),
+            Some("https://mensa.example.com")
+        );
+
+        // every hop is trusted, so the first element is used
+        let headers = header_map(&[(
+            "forwarded",
+            "for=10.0.0.3;host=first.example.com, for=\"[::1]:4711\";host=second",
+        )]);
+        assert_eq!(
+            from_headers(&headers, &forwarded_config(&["10.0.0.0/8", "::1"])).as_deref(),
+            Some("http://first.example.com")
+        );
+    }
+
+    #[test]
+    fn invalid_forwarded_values_are_ignored() {
+        let config = forwarded_config(&[]);
+        for pairs in [
+            [
+                ("x-forwarded-host", "evil.example.com/path"),
+                ("x-forwarded-proto", "https"),
+            ],
+            [
+                ("x-forwarded-host", "mensa.example.com"),
+                ("x-forwarded-proto", "javascript"),
+            ],
+            [
+                ("x-forwarded-host", "mensa.example.com"),
+                ("x-forwarded-prefix", "openmensa"),
+            ],
+            [
+                ("x-forwarded-host", "mensa.example.com"),
+                ("x-forwarded-prefix", "/a\"b"),
+            ],
+        ] {
+            assert_eq!(
+                from_headers(&header_map(&pairs), &config),
+                None,
+                "{:?}",
+                pairs
+            );
+        }
+    }
+
+    #[test]
+    fn headers_of_untrusted_peers_are_ignored() {
+        let headers = header_map(&[
+            (
+                "forwarded",
+                "for=10.0.0.1;host=spoofed.example.com;proto=https",
+            ),
+            ("x-forwarded-host", "spoofed.example.com"),
+        ]);
+        let config = forwarded_config(&["127.0.0.1"]);
+        assert_eq!(
+            forwarded_url(&config, Some(&tcp("10.0.0.1")), &headers),
+            None
+        );
+        assert_eq!(
+            forwarded_url(&config, Some(&PeerAddr::Unix), &headers),
+            None
+        );
+        assert_eq!(forwarded_url(&config, None, &headers), None);
+
+        let disabled = ForwardedConfig {
+            enabled: false,
+            ..config.clone()
+        };
+        assert_eq!(
+            forwarded_url(&disabled, Some(&tcp("127.0.0.1")), &headers),
+            None
+        );
+        assert_eq!(
+            forwarded_url(&config, Some(&tcp("127.0.0.1")), &headers).as_deref(),
+            Some("https://spoofed.example.com")
+        );
+    }
+}
@@ -1,9 +1,32 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
+pub mod api;
+pub mod cache;
+pub mod canteens;
+pub mod config;
+pub mod discovery;
+pub mod fallback;
 pub mod feed;
+pub mod forwarded;
+pub mod listen;
+pub mod metrics;
+pub mod prefetch;
+pub mod reload;
+pub mod schedule;
+pub mod shutdown;
+pub mod singleflight;
+pub mod status;
+pub mod systemd;
+pub mod upstream;
+pub mod webhook;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub deploy_url: Option<String>,
-    pub registered_canteens: HashMap<String, String>, // identifier:canteenId
+    pub canteens: Arc<canteens::CanteenRegistry>,
+    pub cache: Arc<cache::ResponseCache>,
+    pub last_known_good: Arc<fallback::LastKnownGood>,
+    pub history: Option<Arc<openmensa_parser_darmstadt::history::History>>,
+    pub in_flight: Arc<singleflight::SingleFlight<upstream::FetchKey, upstream::MenuItems>>,
+    pub status: Arc<status::UpstreamStatus>,
+    pub probe: Arc<status::UpstreamProbe>,
 }
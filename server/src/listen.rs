@@ -0,0 +1,194 @@
+use std::{
+    os::{
+        fd::{FromRawFd, IntoRawFd, RawFd},
+        unix::fs::{FileTypeExt, PermissionsExt},
+    },
+    path::PathBuf,
+};
+
+use anyhow::Context;
+use tokio::net::{TcpListener, UnixListener};
+
+const UNIX_PREFIX: &str = "unix:";
+const SD_LISTEN_FDS_START: RawFd = 3; // first file descriptor passed by systemd
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener, Option<PathBuf>), // the socket file to remove on shutdown, if we created it
+}
+
+impl Listener {
+    /// Binds to `host:port` or `unix:/path`. A stale socket file nothing listens on anymore is
+    /// replaced.
+    pub async fn bind(address: &str, socket_mode: Option<u32>) -> anyhow::Result<Self> {
+        let path = match address.strip_prefix(UNIX_PREFIX) {
+            Some(path) => PathBuf::from(path),
+            None => return Ok(Self::Tcp(TcpListener::bind(address).await?)),
+        };
+
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if !metadata.file_type().is_socket() {
+                anyhow::bail!("{} exists and is not a socket", path.display());
+            }
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                anyhow::bail!("{} is in use by another process", path.display());
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        if let Some(mode) = socket_mode {
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))
+                .with_context(|| format!("failed to set permissions of {}", path.display()))?;
+        }
+
+        Ok(Self::Unix(listener, Some(path)))
+    }
+
+    /// Takes over the socket passed by systemd socket activation, if the server was started that
+    /// way.
+    pub fn from_systemd() -> anyhow::Result<Option<Self>> {
+        let fds = listen_fds(
+            std::env::var("LISTEN_PID").ok().as_deref(),
+            std::env::var("LISTEN_FDS").ok().as_deref(),
+            std::process::id(),
+        )?;
+        match fds {
+            0 => return Ok(None),
+            1 => {}
+            n => tracing::warn!("systemd passed {} sockets, only using the first one", n),
+        }
+
+        // SAFETY: systemd hands the passed sockets over to this process, nothing else owns them
+        unsafe { Self::from_raw_fd(SD_LISTEN_FDS_START) }.map(Some)
+    }
+
+    /// Wraps a listening tcp or unix socket.
+    ///
+    /// # Safety
+    ///
+    /// The file descriptor must be an open socket owned by nothing else.
+    unsafe fn from_raw_fd(fd: RawFd) -> anyhow::Result<Self> {
+        // SAFETY: guaranteed by the caller
+        let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+        // the address of a socket that is not a unix socket can not be read as a unix address
+        if unix.local_addr().is_ok() {
+            unix.set_nonblocking(true)?;
+            return Ok(Self::Unix(UnixListener::from_std(unix)?, None));
+        }
+
+        // SAFETY: the file descriptor was released by the unix listener above
+        let tcp = unsafe { std::net::TcpListener::from_raw_fd(unix.into_raw_fd()) };
+        tcp.local_addr()
+            .context("the socket passed by systemd is neither a tcp nor a unix socket")?;
+        tcp.set_nonblocking(true)?;
+        Ok(Self::Tcp(TcpListener::from_std(tcp)?))
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => addr.to_string(),
+                Err(_) => "tcp socket".into(),
+            },
+            Self::Unix(listener, _) => match listener.local_addr().ok().and_then(|addr| {
+                addr.as_pathname()
+                    .map(|path| format!("{}{}", UNIX_PREFIX, path.display()))
+            }) {
+                Some(addr) => addr,
+                None => "unix socket".into(),
+            },
+        }
+    }
+}
+
+/// The number of sockets passed to this process from the `LISTEN_PID` and `LISTEN_FDS`
+/// variables, 0 if it was not socket activated.
+fn listen_fds(pid: Option<&str>, fds: Option<&str>, own_pid: u32) -> anyhow::Result<u32> {
+    // the variables may be inherited from a parent that was activated itself
+    if pid.and_then(|v| v.parse::<u32>().ok()) != Some(own_pid) {
+        return Ok(0);
+    }
+
+    fds.context("LISTEN_PID is set but LISTEN_FDS is not")?
+        .parse::<u32>()
+        .context("invalid LISTEN_FDS")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn socket_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("listen-{}-{}.sock", std::process::id(), name))
+    }
+
+    #[tokio::test]
+    async fn unix_sockets_get_the_socket_mode() {
+        let path = socket_path("mode");
+        let listener = Listener::bind(&format!("unix:{}", path.display()), Some(0o660))
+            .await
+            .unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        assert_eq!(listener.describe(), format!("unix:{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_sockets_are_replaced() {
+        let path = socket_path("stale");
+        let address = format!("unix:{}", path.display());
+        // dropping a listener leaves its socket file behind
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+        let listener = Listener::bind(&address, None).await.unwrap();
+        let e = Listener::bind(&address, None).await.err().unwrap();
+        assert!(e.to_string().contains("in use"), "{}", e);
+
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn other_files_are_not_replaced() {
+        let path = socket_path("file");
+        std::fs::write(&path, "").unwrap();
+
+        let e = Listener::bind(&format!("unix:{}", path.display()), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("is not a socket"), "{}", e);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn passed_sockets_are_detected() {
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        // SAFETY: the descriptor was released by the listener
+        let listener = unsafe { Listener::from_raw_fd(tcp.into_raw_fd()) }.unwrap();
+        assert!(matches!(listener, Listener::Tcp(_)));
+        assert_eq!(listener.describe(), addr.to_string());
+
+        let path = socket_path("passed");
+        let unix = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        // SAFETY: the descriptor was released by the listener
+        let listener = unsafe { Listener::from_raw_fd(unix.into_raw_fd()) }.unwrap();
+        assert!(matches!(listener, Listener::Unix(_, None)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn listen_fds_are_only_read_for_this_process() {
+        assert_eq!(listen_fds(Some("42"), Some("1"), 42).unwrap(), 1);
+        assert_eq!(listen_fds(Some("42"), Some("2"), 42).unwrap(), 2);
+        assert_eq!(listen_fds(Some("41"), Some("1"), 42).unwrap(), 0);
+        assert_eq!(listen_fds(None, Some("1"), 42).unwrap(), 0);
+        assert!(listen_fds(Some("42"), None, 42).is_err());
+        assert!(listen_fds(Some("42"), Some("x"), 42).is_err());
+    }
+}
@@ -1,60 +1,180 @@
-use std::{collections::HashMap, fs::File, io::BufReader};
+use std::{path::PathBuf, sync::Arc};
+
+use clap::Parser;
+
+use openmensa_parser_darmstadt::history::History;
 
 use axum_prometheus::PrometheusMetricLayerBuilder;
-use openmensa_parser_darmstadt_server::{AppState, feed};
-
-#[derive(Debug, serde::Deserialize)]
-#[serde(rename_all = "camelCase")]
-struct Config {
-    canteens: HashMap<String, Vec<String>>,
-    deploy_url: Option<String>,
-    bind: Option<String>,
+use openmensa_parser_darmstadt_server::{
+    AppState, api,
+    cache::ResponseCache,
+    canteens::{CanteenRegistry, Canteens},
+    config::Config,
+    discovery,
+    fallback::LastKnownGood,
+    feed,
+    listen::Listener,
+    metrics, prefetch, reload,
+    shutdown::{self, RequestTracker},
+    singleflight::SingleFlight,
+    status::{self, UpstreamProbe, UpstreamStatus},
+    systemd, webhook,
+};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    #[arg(help = "Config file (.json, .toml, .yaml or .yml), defaults to config.json if present")]
+    config: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Validate the config, print the effective config and exit"
+    )]
+    check_config: bool,
 }
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
+    let args = Args::parse();
     tracing_subscriber::fmt::init();
 
-    let config_path = match std::env::args().nth(1) {
-        Some(v) => v,
-        None => "config.json".into(),
+    let config_path = args.config.or_else(|| {
+        let default = PathBuf::from("config.json");
+        default.exists().then_some(default)
+    });
+    let config = match Config::load(config_path.as_deref()) {
+        Ok(v) => Arc::new(v),
+        Err(e) => {
+            match args.check_config {
+                true => eprintln!("invalid config: {:#}", e),
+                false => tracing::error!("failed to load config: {:#}", e),
+            }
+            std::process::exit(1);
+        }
     };
-    let file = File::open(config_path)
-        .expect("failed to open config file, please set the path accordingly");
-    let reader = BufReader::new(file);
-    let config: Config =
-        serde_json::from_reader(reader).expect("failed to deserialize: invalid config file");
 
-    let mut registered_canteens = HashMap::new();
-    for (id, identifiers) in config.canteens {
-        for identifier in identifiers {
-            registered_canteens.insert(identifier, id.clone());
+    if args.check_config {
+        match serde_json::to_string_pretty(&config.redacted()) {
+            Ok(v) => println!("{}", v),
+            Err(e) => eprintln!("failed to print config: {:?}", e),
         }
+        return;
     }
 
-    let prometheus_prefix =
-        std::env::var("PROMETHEUS_PREFIX").unwrap_or(std::env!("CARGO_PKG_NAME").into());
+    let last_known_good = match LastKnownGood::load(config.last_known_good.clone()) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("failed to load last known good snapshots: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let history = match config.history.as_deref().map(History::open).transpose() {
+        Ok(v) => v.map(Arc::new),
+        Err(e) => {
+            tracing::error!("failed to open history: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let prometheus_prefix = config
+        .prometheus_prefix
+        .clone()
+        .or_else(|| std::env::var("PROMETHEUS_PREFIX").ok())
+        .unwrap_or(std::env!("CARGO_PKG_NAME").into());
+    metrics::init(&prometheus_prefix);
     let (prometheus_layer, metric_handle) = PrometheusMetricLayerBuilder::new()
         .with_prefix(prometheus_prefix)
         .enable_response_body_size(true)
-        .with_default_metrics()
+        .with_metrics_from_fn(metrics::install_recorder)
         .build_pair();
 
+    let state = AppState {
+        canteens: Arc::new(CanteenRegistry::new(Canteens::from_config(config.clone()))),
+        cache: Arc::new(ResponseCache::new(config.cache_ttl.clone())),
+        last_known_good: Arc::new(last_known_good),
+        history,
+        in_flight: Arc::new(SingleFlight::default()),
+        status: Arc::new(UpstreamStatus::default()),
+        probe: Arc::new(UpstreamProbe::default()),
+    };
+
+    if let Err(e) = discovery::discover(&state).await {
+        tracing::warn!("failed to discover canteens: {:?}", e);
+    }
+    discovery::spawn(state.clone());
+    prefetch::spawn(state.clone());
+    webhook::spawn(state.clone());
+    if let Some(config_path) = config_path {
+        reload::spawn(state.clone(), config_path);
+    }
+
+    let tracker = Arc::new(RequestTracker::default());
     let app = axum::Router::new()
         .nest("/feed", feed::router())
+        .nest("/api", api::router())
+        .merge(status::router())
         .route(
             "/metrics",
             axum::routing::get(|| async move { metric_handle.render() }),
         )
-        .with_state(AppState {
-            deploy_url: config.deploy_url,
-            registered_canteens,
-        })
+        .with_state(state.clone())
+        .layer(axum::middleware::from_fn_with_state(
+            tracker.clone(),
+            shutdown::track,
+        ))
         .layer(tower_http::trace::TraceLayer::new_for_http())
         .layer(prometheus_layer);
 
-    let bind = config.bind.unwrap_or("0.0.0.0:3000".into());
-    tracing::info!("binding to {}", bind);
-    let listener = tokio::net::TcpListener::bind(bind).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let socket_mode = config.socket_mode().unwrap_or_default();
+    let listener = match Listener::from_systemd() {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            let bind = config.bind.clone().unwrap_or("0.0.0.0:3000".into());
+            match Listener::bind(&bind, socket_mode).await {
+                Ok(v) => v,
+                Err(e) => {
+                    tracing::error!("failed to bind to {}: {:?}", bind, e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Err(e) => {
+            tracing::error!("failed to use the socket passed by systemd: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("listening on {}", listener.describe());
+
+    systemd::notify("READY=1");
+    systemd::spawn_watchdog();
+    let (res, socket_path) = match listener {
+        Listener::Tcp(listener) => (shutdown::serve(listener, app, &state, &tracker).await, None),
+        Listener::Unix(listener, path) => {
+            (shutdown::serve(listener, app, &state, &tracker).await, path)
+        }
+    };
+
+    if let Err(e) = state.last_known_good.persist().await {
+        tracing::error!("failed to persist last known good snapshots: {:?}", e);
+    }
+    if let Some(path) = socket_path
+        && let Err(e) = std::fs::remove_file(&path)
+    {
+        tracing::warn!("failed to remove socket {}: {:?}", path.display(), e);
+    }
+
+    let aborted = match res {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::error!("server error: {:?}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!(
+        "shut down after {} seconds, served {} requests, aborted {} requests",
+        tracker.uptime().as_secs(),
+        tracker.served(),
+        aborted
+    );
 }
@@ -0,0 +1,100 @@
+use std::{sync::OnceLock, time::Duration};
+
+use axum_prometheus::{
+    metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle},
+    utils::SECONDS_DURATION_BUCKETS,
+};
+
+static PREFIX: OnceLock<String> = OnceLock::new();
+
+pub fn init(prefix: &str) {
+    let _ = PREFIX.set(prefix.replace('-', "_"));
+}
+
+/// Installs the global recorder. Unlike the axum-prometheus default this configures buckets for
+/// every `*_duration_seconds` metric, so the upstream latency is exported as a histogram as well.
+pub fn install_recorder() -> PrometheusHandle {
+    let recorder = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("_duration_seconds".into()),
+            SECONDS_DURATION_BUCKETS,
+        )
+        .expect("invalid histogram buckets")
+        .build_recorder();
+
+    let handle = recorder.handle();
+    let upkeep_handle = handle.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            upkeep_handle.run_upkeep();
+        }
+    });
+
+    metrics::set_global_recorder(recorder).expect("failed to set global recorder");
+    handle
+}
+
+fn name(metric: &str) -> String {
+    match PREFIX.get() {
+        Some(prefix) => format!("{}_{}", prefix, metric),
+        None => metric.to_string(),
+    }
+}
+
+pub fn cache_hit(feed: &'static str, format: &'static str) {
+    metrics::counter!(name("cache_hits_total"), "feed" => feed, "format" => format).increment(1);
+}
+
+pub fn cache_miss(feed: &'static str, format: &'static str) {
+    metrics::counter!(name("cache_misses_total"), "feed" => feed, "format" => format).increment(1);
+}
+
+pub fn request_coalesced() {
+    metrics::counter!(name("coalesced_requests_total")).increment(1);
+}
+
+pub fn upstream_request(query: &'static str, duration: Duration) {
+    metrics::histogram!(name("upstream_request_duration_seconds"), "query" => query)
+        .record(duration.as_secs_f64());
+}
+
+pub fn upstream_error(query: &'static str, kind: &'static str) {
+    metrics::counter!(name("upstream_errors_total"), "query" => query, "kind" => kind).increment(1);
+}
+
+pub fn menu_items(canteen_id: &str, days_ahead: i64, count: usize) {
+    metrics::gauge!(
+        name("menu_items"),
+        "canteen" => canteen_id.to_string(),
+        "days_ahead" => days_ahead.to_string()
+    )
+    .set(count as f64);
+}
+
+pub fn unknown_codes(canteen_id: &str, kind: &'static str, count: usize) {
+    metrics::gauge!(
+        name("unknown_codes"),
+        "canteen" => canteen_id.to_string(),
+        "kind" => kind
+    )
+    .set(count as f64);
+}
+
+pub fn average_price(canteen_id: &str, role: &'static str, value: f64) {
+    metrics::gauge!(
+        name("average_price_euros"),
+        "canteen" => canteen_id.to_string(),
+        "role" => role
+    )
+    .set(value);
+}
+
+pub fn prefetch(feed: &str, result: &'static str) {
+    metrics::counter!(name("prefetches_total"), "feed" => feed.to_string(), "result" => result)
+        .increment(1);
+}
+
+pub fn webhook_delivery(result: &'static str) {
+    metrics::counter!(name("webhook_deliveries_total"), "result" => result).increment(1);
+}
@@ -0,0 +1,430 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    time::Duration,
+};
+
+use chrono::NaiveDateTime;
+use openmensa_parser_darmstadt::filter::MealFilter;
+
+use crate::{
+    AppState,
+    cache::{CacheKey, CacheTtl},
+    canteens::Canteens,
+    config::FeedConfig,
+    feed::v2::{FeedKind, Format, render_fetched},
+    metrics,
+    schedule::CronSchedule,
+    upstream,
+};
+
+const MAX_SLEEP: Duration = Duration::from_secs(60); // picks up reloaded schedules
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrefetchConfig {
+    pub enabled: bool,
+    pub lead: u64,   // seconds before a scheduled poll to refresh the cache
+    pub jitter: u64, // up to this many seconds of additional lead, spread between canteens
+}
+
+impl PrefetchConfig {
+    /// Checks that prefetched responses can be served to the poll they are made for. They are
+    /// cached under the configured deploy url and must not expire before the poll.
+    pub fn validate(
+        &self,
+        cache_ttl: &CacheTtl,
+        feeds: &[&FeedConfig],
+        deploy_url: Option<&str>,
+        forwarded: bool,
+    ) -> anyhow::Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+        if forwarded && deploy_url.is_none() {
+            anyhow::bail!(
+                "prefetch: deployUrl must be set when forwarded is enabled, prefetched responses \
+                 are cached for the deploy url"
+            );
+        }
+
+        for feed in feeds {
+            let kind = match FeedKind::parse(&feed.name) {
+                Some(FeedKind::Day(_)) | None => continue,
+                Some(v) => v,
+            };
+            let ttl = cache_ttl.seconds(kind);
+            if self.lead + self.jitter >= ttl {
+                anyhow::bail!(
+                    "prefetch: lead and jitter ({} seconds) must be less than the cache ttl of the \
+                     {} feed ({} seconds)",
+                    self.lead + self.jitter,
+                    feed.name,
+                    ttl
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for PrefetchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            lead: 60,
+            jitter: 120,
+        }
+    }
+}
+
+type JobKey = (String, String); // canteenId, feed name
+
+/// A failed prefetch, retried in the intervals OpenMensa retries the poll itself.
+struct Retry {
+    poll: NaiveDateTime,
+    attempt: u32,
+}
+
+/// Parses the `retry` attribute of a feed schedule, `<minutes> [<count> ...]`.
+fn parse_retry(retry: Option<&str>) -> Option<(chrono::Duration, u32)> {
+    let mut parts = retry?.split_whitespace();
+    let minutes = parts.next()?.parse::<i64>().ok().filter(|m| *m > 0)?;
+    let count = match parts.next() {
+        Some(count) => count.parse::<u32>().ok()?,
+        None => 1,
+    };
+    Some((chrono::Duration::minutes(minutes), count))
+}
+
+/// How long before a poll the canteen is refreshed. The jitter is derived from the canteen id,
+/// so canteens sharing a schedule do not hit upstream at the same time.
+fn offset(config: &PrefetchConfig, canteen_id: &str) -> chrono::Duration {
+    let mut hasher = DefaultHasher::new();
+    canteen_id.hash(&mut hasher);
+    let jitter = hasher.finish() % (config.jitter + 1);
+    chrono::Duration::seconds((config.lead + jitter) as i64)
+}
+
+/// Fetches the menu items of a feed once and caches the default xml response of every
+/// identifier of the canteen.
+async fn prefetch(
+    state: &AppState,
+    canteens: &Canteens,
+    canteen_id: &str,
+    kind: FeedKind,
+) -> anyhow::Result<()> {
+    let (from, to) = kind.range(chrono::Local::now().date_naive());
+    let fetched = upstream::fetch(state, canteen_id, from, to)
+        .await
+        .map_err(|e| anyhow::anyhow!("{:#}", e))?;
+    if fetched.stale_age.is_some() {
+        anyhow::bail!("upstream unavailable, not caching the last known good snapshot");
+    }
+
+    for (identifier, id) in &canteens.registered {
+        if id != canteen_id {
+            continue;
+        }
+
+        // requests with forwarded feed urls use their own cache entries, config validation
+        // ensures deployUrl is set so the proxy can forward the same url
+        let key = CacheKey {
+            identifier: identifier.clone(),
+            kind,
+            from,
+            to,
+            format: Format::Xml,
+            filter: MealFilter::default(),
+            deploy_url: canteens.deploy_url().map(str::to_string),
+        };
+        render_fetched(state, key, canteen_id, &fetched)?;
+    }
+
+    Ok(())
+}
+
+/// Tracks which polls were prefetched and which prefetches are retried.
+#[derive(Default)]
+struct Jobs {
+    done: HashMap<JobKey, NaiveDateTime>, // the poll last prefetched
+    retries: HashMap<JobKey, Retry>,
+}
+
+impl Jobs {
+    /// Returns the poll and retry attempt to prefetch for now, or when to check again.
+    fn due(
+        &self,
+        key: &JobKey,
+        poll: NaiveDateTime,
+        retry: Option<(chrono::Duration, u32)>,
+        offset: chrono::Duration,
+        now: NaiveDateTime,
+    ) -> Result<(NaiveDateTime, u32), Option<NaiveDateTime>> {
+        let scheduled = (self.done.get(key) != Some(&poll)).then_some((poll, 0));
+        let retried = self
+            .retries
+            .get(key)
+            .zip(retry)
+            .map(|(r, (interval, _))| (r.poll + interval * r.attempt as i32, r.attempt));
+
+        let mut next: Option<NaiveDateTime> = None;
+        for (at, attempt) in [scheduled, retried].into_iter().flatten() {
+            if at - offset <= now {
+                return Ok((at, attempt));
+            }
+            next = Some(next.map_or(at - offset, |next| next.min(at - offset)));
+        }
+        Err(next)
+    }
+
+    fn record(
+        &mut self,
+        key: JobKey,
+        at: NaiveDateTime,
+        attempt: u32,
+        retry: Option<(chrono::Duration, u32)>,
+        res: anyhow::Result<()>,
+    ) {
+        let poll = match attempt {
+            0 => {
+                self.done.insert(key.clone(), at);
+                at
+            }
+            _ => self.retries.get(&key).map_or(at, |r| r.poll),
+        };
+
+        match (res, retry) {
+            (Ok(()), _) => {
+                metrics::prefetch(&key.1, "success");
+                self.retries.remove(&key);
+            }
+            (Err(e), Some((_, count))) if attempt < count => {
+                metrics::prefetch(&key.1, "error");
+                tracing::warn!(
+                    "failed to prefetch the {} feed of canteen {}, retrying: {:#}",
+                    key.1,
+                    key.0,
+                    e
+                );
+                self.retries.insert(
+                    key,
+                    Retry {
+                        poll,
+                        attempt: attempt + 1,
+                    },
+                );
+            }
+            (Err(e), _) => {
+                metrics::prefetch(&key.1, "error");
+                tracing::warn!(
+                    "failed to prefetch the {} feed of canteen {}: {:#}",
+                    key.1,
+                    key.0,
+                    e
+                );
+                self.retries.remove(&key);
+            }
+        }
+    }
+}
+
+/// Refreshes the cache shortly before OpenMensa polls the advertised feeds of each canteen.
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let mut jobs = Jobs::default();
+
+        loop {
+            let canteens = state.canteens.get();
+            let config = &canteens.config.prefetch;
+            let now = chrono::Local::now().naive_local();
+            let mut wake = now + MAX_SLEEP;
+
+            for (canteen_id, feeds) in canteens.feeds.iter().filter(|_| config.enabled) {
+                let offset = offset(config, canteen_id);
+
+                for feed in feeds {
+                    // feeds not named after a feed kind are not served by this server
+                    let kind = match FeedKind::parse(&feed.name) {
+                        Some(FeedKind::Day(_)) | None => continue,
+                        Some(v) => v,
+                    };
+                    let schedule = feed.schedule();
+                    let poll = match CronSchedule::parse(&schedule)
+                        .ok()
+                        .and_then(|cron| cron.next_after(now))
+                    {
+                        Some(v) => v,
+                        None => continue,
+                    };
+                    let retry = parse_retry(schedule.retry.as_deref());
+
+                    let key = (canteen_id.clone(), feed.name.clone());
+                    match jobs.due(&key, poll, retry, offset, now) {
+                        Ok((at, attempt)) => {
+                            let res = prefetch(&state, &canteens, canteen_id, kind).await;
+                            jobs.record(key, at, attempt, retry, res);
+                        }
+                        Err(Some(next)) => wake = wake.min(next),
+                        Err(None) => {}
+                    }
+                }
+            }
+
+            let now = chrono::Local::now().naive_local();
+            let sleep = (wake - now).to_std().unwrap_or_default().min(MAX_SLEEP);
+            tokio::time::sleep(sleep).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2026-01-05 {}", time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn key() -> JobKey {
+        ("1".into(), "today".into())
+    }
+
+    #[test]
+    fn parse_retry_reads_minutes_and_count() {
+        let minutes = chrono::Duration::minutes;
+        assert_eq!(parse_retry(Some("30 1")), Some((minutes(30), 1)));
+        assert_eq!(parse_retry(Some("60 5 1440")), Some((minutes(60), 5)));
+        assert_eq!(parse_retry(Some("15")), Some((minutes(15), 1)));
+        assert_eq!(parse_retry(Some("0 3")), None);
+        assert_eq!(parse_retry(Some("x")), None);
+        assert_eq!(parse_retry(Some("30 x")), None);
+        assert_eq!(parse_retry(Some("")), None);
+        assert_eq!(parse_retry(None), None);
+    }
+
+    #[test]
+    fn polls_are_due_once_within_the_offset() {
+        let mut jobs = Jobs::default();
+        let offset = chrono::Duration::minutes(2);
+        let poll = at("06:00");
+
+        assert_eq!(
+            jobs.due(&key(), poll, None, offset, at("05:00")),
+            Err(Some(at("05:58")))
+        );
+        assert_eq!(
+            jobs.due(&key(), poll, None, offset, at("05:58")),
+            Ok((poll, 0))
+        );
+
+        jobs.record(key(), poll, 0, None, Ok(()));
+        assert_eq!(jobs.due(&key(), poll, None, offset, at("05:59")), Err(None));
+        assert_eq!(
+            jobs.due(&key(), at("07:00"), None, offset, at("06:00")),
+            Err(Some(at("06:58")))
+        );
+    }
+
+    #[test]
+    fn failed_prefetches_are_retried_in_the_retry_interval() {
+        let mut jobs = Jobs::default();
+        let offset = chrono::Duration::minutes(2);
+        let retry = parse_retry(Some("30 2"));
+        let poll = at("06:00");
+
+        jobs.record(key(), poll, 0, retry, Err(anyhow::anyhow!("upstream down")));
+        assert_eq!(
+            jobs.due(&key(), poll, retry, offset, at("06:00")),
+            Err(Some(at("06:28")))
+        );
+        assert_eq!(
+            jobs.due(&key(), poll, retry, offset, at("06:28")),
+            Ok((at("06:30"), 1))
+        );
+
+        jobs.record(
+            key(),
+            at("06:30"),
+            1,
+            retry,
+            Err(anyhow::anyhow!("upstream down")),
+        );
+        assert_eq!(
+            jobs.due(&key(), poll, retry, offset, at("06:58")),
+            Ok((at("07:00"), 2))
+        );
+
+        // the last retry gives up until the next scheduled poll
+        jobs.record(
+            key(),
+            at("07:00"),
+            2,
+            retry,
+            Err(anyhow::anyhow!("upstream down")),
+        );
+        assert_eq!(
+            jobs.due(&key(), poll, retry, offset, at("08:00")),
+            Err(None)
+        );
+    }
+
+    #[test]
+    fn successful_retries_stop_retrying() {
+        let mut jobs = Jobs::default();
+        let offset = chrono::Duration::zero();
+        let retry = parse_retry(Some("30 5"));
+        let poll = at("06:00");
+
+        jobs.record(key(), poll, 0, retry, Err(anyhow::anyhow!("upstream down")));
+        jobs.record(key(), at("06:30"), 1, retry, Ok(()));
+        assert_eq!(
+            jobs.due(&key(), poll, retry, offset, at("09:00")),
+            Err(None)
+        );
+    }
+
+    #[test]
+    fn validate_rejects_prefetches_that_expire_before_the_poll() {
+        let config: crate::config::Config = serde_json::from_value(serde_json::json!({
+            "deployUrl": "https://example.com",
+        }))
+        .unwrap();
+        let feeds: Vec<&FeedConfig> = config.feeds.iter().collect();
+        let ttl = CacheTtl {
+            full: 900,
+            today: 300,
+        };
+
+        let prefetch = |lead, jitter| PrefetchConfig {
+            enabled: true,
+            lead,
+            jitter,
+        };
+        assert!(
+            prefetch(60, 120)
+                .validate(&ttl, &feeds, config.deploy_url.as_deref(), false)
+                .is_ok()
+        );
+        let e = prefetch(200, 100)
+            .validate(&ttl, &feeds, config.deploy_url.as_deref(), false)
+            .unwrap_err();
+        assert!(e.to_string().contains("today feed"), "{}", e);
+        assert!(
+            prefetch(200, 100)
+                .validate(&ttl, &[], config.deploy_url.as_deref(), false)
+                .is_ok()
+        );
+
+        assert!(
+            prefetch(60, 120)
+                .validate(&ttl, &feeds, None, true)
+                .is_err()
+        );
+        assert!(
+            prefetch(60, 120)
+                .validate(&ttl, &feeds, config.deploy_url.as_deref(), true)
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::signal::unix::{SignalKind, signal};
+
+use crate::{AppState, canteens::Canteens, config::Config, discovery};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Loads and validates the config file and swaps it in. The current config stays active if the
+/// new one is invalid.
+pub async fn reload(state: &AppState, path: &Path) -> anyhow::Result<()> {
+    let config = Arc::new(Config::load(Some(path))?);
+
+    let _guard = state.canteens.lock_updates().await;
+    warn_restart_required(&state.canteens.get().config, &config);
+
+    let canteens = match config.discovery.enabled {
+        true => match discovery::build(config.clone()).await {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::warn!(
+                    "failed to discover canteens, using configured canteens only until the next discovery: {:?}",
+                    e
+                );
+                Canteens::from_config(config)
+            }
+        },
+        false => Canteens::from_config(config),
+    };
+
+    let previous = state.canteens.replace(canteens);
+    // cached feeds may advertise outdated feed urls
+    state.cache.clear();
+    state
+        .canteens
+        .get()
+        .log_changes(&previous, "reloaded config");
+
+    Ok(())
+}
+
+fn warn_restart_required(current: &Config, new: &Config) {
+    let changed = [
+        ("bind", current.bind != new.bind),
+        ("socketMode", current.socket_mode != new.socket_mode),
+        ("cacheTtl", current.cache_ttl != new.cache_ttl),
+        (
+            "lastKnownGood",
+            current.last_known_good != new.last_known_good,
+        ),
+        ("history", current.history != new.history),
+        (
+            "prometheusPrefix",
+            current.prometheus_prefix != new.prometheus_prefix,
+        ),
+    ];
+
+    for (field, _) in changed.iter().filter(|(_, changed)| *changed) {
+        tracing::warn!(
+            "changing {} requires a restart, keeping the current value",
+            field
+        );
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Reloads the config on SIGHUP and whenever the config file changes.
+pub fn spawn(state: AppState, path: PathBuf) {
+    tokio::spawn(async move {
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(v) => v,
+            Err(e) => {
+                tracing::error!("failed to listen for SIGHUP: {:?}", e);
+                return;
+            }
+        };
+
+        let mut last_modified = modified(&path);
+        let mut poll = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    tracing::info!("received SIGHUP, reloading config");
+                }
+                _ = poll.tick() => {
+                    if modified(&path) == last_modified {
+                        continue;
+                    }
+                    tracing::info!("config file changed, reloading config");
+                }
+            }
+
+            last_modified = modified(&path);
+            if let Err(e) = reload(&state, &path).await {
+                tracing::error!("failed to reload config, keeping the current one: {:?}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,134 @@
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use openmensa_parser_darmstadt::openmensa;
+
+/// Cron-like evaluation of the `<schedule>` fields OpenMensa uses to poll feeds.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days_of_month: Option<Vec<u32>>, // None means "*"
+    months: Vec<u32>,
+    days_of_week: Option<Vec<u32>>, // None means "*", sunday is 0
+}
+
+impl CronSchedule {
+    pub fn parse(schedule: &openmensa::Schedule) -> anyhow::Result<Self> {
+        let days_of_week = parse_optional(schedule.day_of_week.as_deref(), 0, 7)?.map(|days| {
+            let mut days: Vec<u32> = days.into_iter().map(|d| d % 7).collect();
+            days.sort_unstable();
+            days.dedup();
+            days
+        });
+
+        Ok(Self {
+            minutes: parse_field(schedule.minute.as_deref().unwrap_or("0"), 0, 59)?,
+            hours: parse_field(&schedule.hour, 0, 23)?,
+            days_of_month: parse_optional(schedule.day_of_month.as_deref(), 1, 31)?,
+            months: parse_field(schedule.month.as_deref().unwrap_or("*"), 1, 12)?,
+            days_of_week,
+        })
+    }
+
+    fn matches_date(&self, date: chrono::NaiveDate) -> bool {
+        if !self.months.contains(&date.month()) {
+            return false;
+        }
+
+        let day_of_month = self
+            .days_of_month
+            .as_ref()
+            .map(|days| days.contains(&date.day()));
+        let day_of_week = self
+            .days_of_week
+            .as_ref()
+            .map(|days| days.contains(&date.weekday().num_days_from_sunday()));
+
+        // like cron: if both are restricted, either one has to match
+        match (day_of_month, day_of_week) {
+            (None, None) => true,
+            (Some(v), None) | (None, Some(v)) => v,
+            (Some(a), Some(b)) => a || b,
+        }
+    }
+
+    /// Returns the first scheduled time strictly after `after`.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut date = after.date();
+
+        // a schedule that matches at all matches within a little more than four years
+        for _ in 0..(366 * 4 + 1) {
+            if self.matches_date(date) {
+                for hour in &self.hours {
+                    for minute in &self.minutes {
+                        let candidate = date.and_hms_opt(*hour, *minute, 0)?;
+                        if candidate > after {
+                            return Some(candidate);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+
+        None
+    }
+}
+
+fn parse_optional(field: Option<&str>, min: u32, max: u32) -> anyhow::Result<Option<Vec<u32>>> {
+    match field.map(str::trim) {
+        None | Some("*") => Ok(None),
+        Some(field) => parse_field(field, min, max).map(Some),
+    }
+}
+
+/// Parses a single cron field such as `*`, `4`, `6-16`, `*/15` or `8,12-14`.
+fn parse_field(field: &str, min: u32, max: u32) -> anyhow::Result<Vec<u32>> {
+    let mut values = Vec::new();
+
+    for part in field.split(',').map(str::trim) {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            anyhow::bail!("invalid step in schedule field \"{}\"", field);
+        }
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (start.parse()?, end.parse()?),
+                None => {
+                    let value = range.parse()?;
+                    match part.contains('/') {
+                        true => (value, max),
+                        false => (value, value),
+                    }
+                }
+            },
+        };
+
+        if start < min || end > max || start > end {
+            anyhow::bail!("schedule field \"{}\" out of range {}-{}", field, min, max);
+        }
+
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+pub fn seconds_until_next(schedule: &openmensa::Schedule, now: NaiveDateTime) -> Option<i64> {
+    let cron = match CronSchedule::parse(schedule) {
+        Ok(v) => v,
+        Err(e) => {
+            tracing::warn!("failed to parse schedule {:?}: {:?}", schedule, e);
+            return None;
+        }
+    };
+
+    cron.next_after(now.with_nanosecond(0)?)
+        .map(|next| (next - now).num_seconds().max(0))
+}
@@ -0,0 +1,137 @@
+use std::{
+    fmt::Debug,
+    future::IntoFuture,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, connect_info::Connected},
+    middleware::Next,
+    response::Response,
+    serve::{IncomingStream, Listener},
+};
+use tokio::signal::unix::{SignalKind, signal};
+
+use crate::{AppState, forwarded::PeerAddr, systemd};
+
+/// Counts requests, so the shutdown summary can tell how many were served and cut off.
+pub struct RequestTracker {
+    started_at: Instant,
+    served: AtomicU64,
+    in_flight: AtomicU64,
+}
+
+impl Default for RequestTracker {
+    fn default() -> Self {
+        Self {
+            started_at: Instant::now(),
+            served: AtomicU64::new(0),
+            in_flight: AtomicU64::new(0),
+        }
+    }
+}
+
+impl RequestTracker {
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn served(&self) -> u64 {
+        self.served.load(Ordering::Relaxed)
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+}
+
+struct InFlight<'a>(&'a RequestTracker);
+
+impl Drop for InFlight<'_> {
+    // also runs for requests dropped mid-response
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Middleware for [`RequestTracker`].
+pub async fn track(
+    axum::extract::State(tracker): axum::extract::State<Arc<RequestTracker>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    tracker.in_flight.fetch_add(1, Ordering::Relaxed);
+    let _in_flight = InFlight(&tracker);
+
+    let response = next.run(request).await;
+    tracker.served.fetch_add(1, Ordering::Relaxed);
+    response
+}
+
+/// Waits for SIGTERM or SIGINT and returns the name of the received signal.
+pub async fn signal_received() -> anyhow::Result<&'static str> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+
+    Ok(tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    })
+}
+
+/// Serves `app` until SIGTERM or SIGINT, then waits up to the shutdown timeout for in-flight
+/// requests. Returns how many requests were still in flight when the timeout expired.
+pub async fn serve<L>(
+    listener: L,
+    app: axum::Router,
+    state: &AppState,
+    tracker: &RequestTracker,
+) -> std::io::Result<u64>
+where
+    L: Listener,
+    L::Addr: Debug,
+    PeerAddr: for<'a> Connected<IncomingStream<'a, L>>,
+{
+    let (draining_tx, mut draining_rx) = tokio::sync::watch::channel(false);
+    let server = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<PeerAddr>(),
+    )
+    .with_graceful_shutdown(async move {
+        match signal_received().await {
+            Ok(signal) => tracing::info!("received {}, draining in-flight requests", signal),
+            Err(e) => {
+                tracing::error!("failed to listen for shutdown signals: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+        systemd::notify("STOPPING=1");
+        let _ = draining_tx.send(true);
+    })
+    .into_future();
+    tokio::pin!(server);
+
+    tokio::select! {
+        res = &mut server => res.map(|_| 0),
+        _ = draining_rx.changed() => {
+            // read at shutdown, so the timeout follows config reloads
+            let timeout = Duration::from_secs(state.canteens.get().config.shutdown_timeout);
+            match tokio::time::timeout(timeout, &mut server).await {
+                Ok(res) => res.map(|_| 0),
+                Err(_) => {
+                    let aborted = tracker.in_flight();
+                    tracing::warn!(
+                        "{} requests still in flight after {} seconds, closing their connections",
+                        aborted,
+                        timeout.as_secs()
+                    );
+                    Ok(aborted)
+                }
+            }
+        }
+    }
+}
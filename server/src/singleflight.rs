@@ -0,0 +1,62 @@
+use std::{collections::HashMap, hash::Hash, sync::Arc, sync::Mutex};
+
+use futures_util::{
+    FutureExt,
+    future::{BoxFuture, Shared},
+};
+
+type SharedResult<T> = Shared<BoxFuture<'static, Result<T, Arc<anyhow::Error>>>>;
+
+/// Deduplicates concurrent calls for the same key, so that only one of them does the actual work
+/// and all others wait for and share its result.
+pub struct SingleFlight<K, T: Clone> {
+    in_flight: Mutex<HashMap<K, SharedResult<T>>>,
+}
+
+impl<K, T> Default for SingleFlight<K, T>
+where
+    T: Clone,
+{
+    fn default() -> Self {
+        Self {
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<K, T> SingleFlight<K, T>
+where
+    K: Eq + Hash + Clone,
+    T: Clone + Send + Sync + 'static,
+{
+    /// Runs `work` unless a call for `key` is already in flight. Returns the result and whether
+    /// it was coalesced into an already running call.
+    pub async fn run<F>(&self, key: K, work: F) -> (Result<T, Arc<anyhow::Error>>, bool)
+    where
+        F: Future<Output = anyhow::Result<T>> + Send + 'static,
+    {
+        let (shared, coalesced) = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(shared) => (shared.clone(), true),
+                None => {
+                    let shared = work.map(|res| res.map_err(Arc::new)).boxed().shared();
+                    in_flight.insert(key.clone(), shared.clone());
+                    (shared, false)
+                }
+            }
+        };
+
+        let res = shared.clone().await;
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight
+            .get(&key)
+            .is_some_and(|current| current.ptr_eq(&shared))
+        {
+            in_flight.remove(&key);
+        }
+
+        (res, coalesced)
+    }
+}
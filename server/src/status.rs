@@ -0,0 +1,166 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing,
+};
+use openmensa_parser_darmstadt::parser::fetch_locations;
+
+use crate::AppState;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const PROBE_INTERVAL: Duration = Duration::from_secs(30); // how long a probe result is reused
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchStatus {
+    pub last_fetch: Option<chrono::DateTime<chrono::Utc>>,
+    pub last_error: Option<FetchError>,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct FetchError {
+    pub at: chrono::DateTime<chrono::Utc>,
+    pub message: String,
+}
+
+/// Outcome of the latest upstream fetches per canteen.
+#[derive(Default)]
+pub struct UpstreamStatus {
+    canteens: Mutex<HashMap<String, FetchStatus>>, // canteenId:status
+}
+
+impl UpstreamStatus {
+    pub fn record_success(&self, canteen_id: &str) {
+        let mut canteens = self.canteens.lock().unwrap();
+        canteens
+            .entry(canteen_id.to_string())
+            .or_default()
+            .last_fetch = Some(chrono::Utc::now());
+    }
+
+    pub fn record_error(&self, canteen_id: &str, error: &anyhow::Error) {
+        let mut canteens = self.canteens.lock().unwrap();
+        canteens
+            .entry(canteen_id.to_string())
+            .or_default()
+            .last_error = Some(FetchError {
+            at: chrono::Utc::now(),
+            message: format!("{:#}", error),
+        });
+    }
+
+    pub fn get(&self, canteen_id: &str) -> FetchStatus {
+        let canteens = self.canteens.lock().unwrap();
+        canteens.get(canteen_id).cloned().unwrap_or_default()
+    }
+}
+
+/// Checks whether upstream answers, reusing the result for a while so frequent readiness checks
+/// do not turn into upstream load.
+#[derive(Default)]
+pub struct UpstreamProbe {
+    last: tokio::sync::Mutex<Option<(Instant, Result<(), String>)>>,
+}
+
+impl UpstreamProbe {
+    pub async fn check(&self) -> Result<(), String> {
+        // holding the lock while probing lets concurrent checks wait for the same result
+        let mut last = self.last.lock().await;
+        if let Some((checked_at, res)) = last.as_ref()
+            && checked_at.elapsed() < PROBE_INTERVAL
+        {
+            return res.clone();
+        }
+
+        let res = match tokio::time::timeout(PROBE_TIMEOUT, fetch_locations()).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(format!("upstream unavailable: {:#}", e)),
+            Err(_) => Err(format!(
+                "upstream did not answer within {} seconds",
+                PROBE_TIMEOUT.as_secs()
+            )),
+        };
+        *last = Some((Instant::now(), res.clone()));
+
+        res
+    }
+}
+
+pub fn router() -> axum::Router<AppState> {
+    axum::Router::new()
+        .route("/healthz", routing::get(get_healthz))
+        .route("/readyz", routing::get(get_readyz))
+        .route("/status", routing::get(get_status))
+}
+
+async fn get_healthz() -> &'static str {
+    "ok"
+}
+
+async fn get_readyz(State(state): State<AppState>) -> Response {
+    if state.canteens.get().registered.is_empty() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "no canteens registered").into_response();
+    }
+
+    match state.probe.check().await {
+        Ok(()) => "ready".into_response(),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e).into_response(),
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CanteenStatus {
+    canteen_id: String,
+    identifiers: Vec<String>,
+    #[serde(flatten)]
+    fetch: FetchStatus,
+    cache_age: Option<u64>, // seconds since the newest cached response
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Status {
+    canteens: Vec<CanteenStatus>,
+}
+
+async fn get_status(State(state): State<AppState>) -> Response {
+    let registry = state.canteens.get();
+    let cache_ages = state.cache.ages();
+
+    let mut identifiers: HashMap<&String, Vec<String>> = HashMap::new();
+    for (identifier, canteen_id) in &registry.registered {
+        identifiers
+            .entry(canteen_id)
+            .or_default()
+            .push(identifier.clone());
+    }
+
+    let mut canteens = identifiers
+        .into_iter()
+        .map(|(canteen_id, mut identifiers)| {
+            identifiers.sort();
+            let cache_age = identifiers
+                .iter()
+                .filter_map(|i| cache_ages.get(i))
+                .min()
+                .map(|age| age.as_secs());
+
+            CanteenStatus {
+                canteen_id: canteen_id.clone(),
+                fetch: state.status.get(canteen_id),
+                identifiers,
+                cache_age,
+            }
+        })
+        .collect::<Vec<_>>();
+    canteens.sort_by(|a, b| a.canteen_id.cmp(&b.canteen_id));
+
+    axum::Json(Status { canteens }).into_response()
+}
@@ -0,0 +1,125 @@
+use std::{os::unix::net::UnixDatagram, time::Duration};
+
+/// Sends a state like `READY=1` to systemd, if the server runs as a `Type=notify` service.
+pub fn notify(state: &str) {
+    if let Err(e) = try_notify(state) {
+        tracing::warn!("failed to notify systemd of {}: {:?}", state, e);
+    }
+}
+
+fn try_notify(state: &str) -> anyhow::Result<()> {
+    match std::env::var("NOTIFY_SOCKET") {
+        Ok(path) => notify_to(&path, state),
+        Err(_) => Ok(()),
+    }
+}
+
+/// Sends a state to a notify socket, a path or an abstract name starting with `@`.
+fn notify_to(path: &str, state: &str) -> anyhow::Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    match path.strip_prefix('@') {
+        Some(name) => send_abstract(&socket, name, state)?,
+        None => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(socket: &UnixDatagram, name: &str, state: &str) -> anyhow::Result<()> {
+    use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+    let addr = SocketAddr::from_abstract_name(name)?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_abstract(_socket: &UnixDatagram, name: &str, _state: &str) -> anyhow::Result<()> {
+    anyhow::bail!("abstract socket @{} is only supported on linux", name)
+}
+
+/// Sends keep-alives at half the interval systemd expects them, if the watchdog is enabled.
+pub fn spawn_watchdog() {
+    let interval = match watchdog_interval(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    ) {
+        Some(v) => v,
+        None => return,
+    };
+
+    tracing::info!(
+        "sending systemd watchdog keep-alives every {} ms",
+        interval.as_millis()
+    );
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// Half the watchdog timeout from `WATCHDOG_USEC`, if the watchdog is enabled for this process.
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    let usec = usec?.parse::<u64>().ok().filter(|v| *v > 0)?;
+    if let Some(pid) = pid
+        && pid.parse::<u32>().ok() != Some(own_pid)
+    {
+        return None;
+    }
+    Some(Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notify_sends_the_state() {
+        let path = std::env::temp_dir().join(format!("notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        notify_to(path.to_str().unwrap(), "READY=1").unwrap();
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn notify_supports_abstract_sockets() {
+        use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+        let name = format!("notify-{}", std::process::id());
+        let receiver =
+            UnixDatagram::bind_addr(&SocketAddr::from_abstract_name(&name).unwrap()).unwrap();
+
+        notify_to(&format!("@{}", name), "WATCHDOG=1").unwrap();
+        let mut buf = [0; 64];
+        let len = receiver.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"WATCHDOG=1");
+    }
+
+    #[test]
+    fn watchdog_interval_is_half_the_timeout() {
+        assert_eq!(
+            watchdog_interval(Some("30000000"), None, 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            watchdog_interval(Some("30000000"), Some("42"), 42),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(watchdog_interval(Some("30000000"), Some("41"), 42), None);
+        assert_eq!(watchdog_interval(Some("0"), None, 42), None);
+        assert_eq!(watchdog_interval(None, None, 42), None);
+    }
+}
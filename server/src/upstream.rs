@@ -0,0 +1,224 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+    time::Instant,
+};
+
+use openmensa_parser_darmstadt::{
+    graphql::{additive_name, allergic_name, error_kind, menu_items::MenuItemsMenuItems},
+    history::History,
+    parser::{fetch_menu_items, item_date},
+};
+
+use crate::{AppState, feed::v2::FeedKind, metrics};
+
+pub type FetchKey = (String, Option<chrono::NaiveDate>, Option<chrono::NaiveDate>);
+pub type MenuItems = Arc<Vec<MenuItemsMenuItems>>;
+
+pub struct Fetched {
+    pub menu_items: MenuItems,
+    pub stale_age: Option<chrono::Duration>, // set when served from the last known good snapshot
+}
+
+/// Fetches the menu items of a canteen, sharing the upstream request with concurrent callers
+/// and falling back to the last known good snapshot if upstream fails.
+pub async fn fetch(
+    state: &AppState,
+    canteen_id: &str,
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+) -> Result<Fetched, Arc<anyhow::Error>> {
+    let last_known_good = state.last_known_good.clone();
+    let history = state.history.clone();
+    let status = state.status.clone();
+    let work = {
+        let canteen_id = canteen_id.to_string();
+        async move {
+            let started = Instant::now();
+            let res = fetch_menu_items(canteen_id.clone(), from, to).await;
+            metrics::upstream_request("menu_items", started.elapsed());
+
+            let menu_items = match res {
+                Ok(v) => v,
+                Err(e) => {
+                    metrics::upstream_error("menu_items", error_kind(&e));
+                    status.record_error(&canteen_id, &e);
+                    return Err(e);
+                }
+            };
+            status.record_success(&canteen_id);
+            record_menu_metrics(&canteen_id, from, to, &menu_items);
+            if keeps_snapshot(from, to, chrono::Local::now().date_naive()) {
+                last_known_good
+                    .store(&canteen_id, from, to, &menu_items)
+                    .await;
+            }
+
+            let menu_items = Arc::new(menu_items);
+            if let Some(history) = history {
+                record_history(history, canteen_id, menu_items.clone());
+            }
+            Ok(menu_items)
+        }
+    };
+
+    let (res, coalesced) = state
+        .in_flight
+        .run((canteen_id.to_string(), from, to), work)
+        .await;
+    if coalesced {
+        metrics::request_coalesced();
+    }
+
+    match res {
+        Ok(menu_items) => Ok(Fetched {
+            menu_items,
+            stale_age: None,
+        }),
+        Err(e) => match state.last_known_good.get(canteen_id, from, to) {
+            Some(snapshot) => {
+                tracing::warn!(
+                    "failed to fetch openmensa data, serving last known good from {}: {:?}",
+                    snapshot.fetched_at,
+                    e
+                );
+                let age = snapshot.age();
+                Ok(Fetched {
+                    menu_items: Arc::new(snapshot.menu_items),
+                    stale_age: Some(age),
+                })
+            }
+            None => Err(e),
+        },
+    }
+}
+
+/// Whether a fetched range gets its own last known good snapshot. Only the ranges of the
+/// advertised feeds do, other ranges are served from a snapshot covering them, so query
+/// parameters can not add snapshots and rewrites of the snapshot file without bound.
+fn keeps_snapshot(
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+    today: chrono::NaiveDate,
+) -> bool {
+    [
+        FeedKind::Full,
+        FeedKind::Today,
+        FeedKind::Week,
+        FeedKind::Next,
+    ]
+    .iter()
+    .any(|kind| kind.range(today) == (from, to))
+}
+
+/// Records the fetched items in the background, so responses do not wait for the database.
+fn record_history(history: Arc<History>, canteen_id: String, menu_items: MenuItems) {
+    tokio::task::spawn_blocking(move || match history.record(&canteen_id, &menu_items) {
+        Ok(0) => {}
+        Ok(recorded) => tracing::debug!(
+            "recorded {} changed menu items of canteen {} in the history",
+            recorded,
+            canteen_id
+        ),
+        Err(e) => tracing::error!("failed to record menu history: {:?}", e),
+    });
+}
+
+const MENU_ITEMS_DAYS_AHEAD: i64 = 14; // days from today the item count gauge covers
+
+/// Records metrics about the fetched data itself, so alerts can fire on empty menus or codes
+/// the parser does not know. Only the full feed range covers every upcoming day, so fetches of
+/// other ranges are ignored rather than overwriting the gauges with a partial view.
+fn record_menu_metrics(
+    canteen_id: &str,
+    from: Option<chrono::NaiveDate>,
+    to: Option<chrono::NaiveDate>,
+    menu_items: &[MenuItemsMenuItems],
+) {
+    let today = chrono::Local::now().date_naive();
+    if (from, to) != FeedKind::Full.range(today) {
+        return;
+    }
+
+    let mut per_day: HashMap<chrono::NaiveDate, usize> = HashMap::new();
+    for item in menu_items {
+        if let Ok(date) = item_date(item) {
+            *per_day.entry(date).or_default() += 1;
+        }
+    }
+
+    // every covered day is set, so days that lost their items drop to 0
+    for days_ahead in 0..MENU_ITEMS_DAYS_AHEAD {
+        let date = today + chrono::Days::new(days_ahead as u64);
+        metrics::menu_items(
+            canteen_id,
+            days_ahead,
+            per_day.get(&date).copied().unwrap_or_default(),
+        );
+    }
+
+    let mut allergens = BTreeSet::new();
+    let mut additives = BTreeSet::new();
+    for item in menu_items {
+        let dish = &item.dish;
+        allergens.extend(
+            dish.allergics
+                .iter()
+                .chain(dish.specific_allergics.iter().flatten())
+                .filter(|code| allergic_name(code).is_none()),
+        );
+        additives.extend(
+            dish.additionals
+                .iter()
+                .filter(|code| additive_name(code).is_none()),
+        );
+    }
+    // the codes themselves are logged, as labels they would grow the metrics without bound
+    for (kind, codes) in [("allergen", allergens), ("additive", additives)] {
+        if !codes.is_empty() {
+            tracing::warn!(
+                "canteen {} uses unknown {} codes: {:?}",
+                canteen_id,
+                kind,
+                codes
+            );
+        }
+        metrics::unknown_codes(canteen_id, kind, codes.len());
+    }
+
+    if !menu_items.is_empty() {
+        let count = menu_items.len() as f64;
+        let student = menu_items.iter().map(|i| i.dish.student_price).sum::<f64>() / count;
+        let guest = menu_items.iter().map(|i| i.dish.guest_price).sum::<f64>() / count;
+        metrics::average_price(canteen_id, "student", student);
+        metrics::average_price(canteen_id, "guest", guest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_feed_ranges_keep_snapshots() {
+        let today: chrono::NaiveDate = "2026-01-07".parse().unwrap();
+        let date = |date: &str| Some(date.parse::<chrono::NaiveDate>().unwrap());
+
+        assert!(keeps_snapshot(Some(today), None, today));
+        assert!(keeps_snapshot(Some(today), Some(today), today));
+        assert!(keeps_snapshot(Some(today), date("2026-01-20"), today));
+        assert!(keeps_snapshot(
+            date("2026-01-05"),
+            date("2026-01-11"),
+            today
+        ));
+
+        assert!(!keeps_snapshot(
+            date("2026-01-08"),
+            date("2026-01-08"),
+            today
+        ));
+        assert!(!keeps_snapshot(Some(today), date("2026-01-21"), today));
+        assert!(!keeps_snapshot(date("2026-01-06"), None, today));
+    }
+}
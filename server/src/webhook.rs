@@ -0,0 +1,684 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+use anyhow::Context;
+use hmac::{KeyInit, Mac};
+use openmensa_parser_darmstadt::{
+    export::dish_type_code, graphql::menu_items::MenuItemsMenuItems, parser::item_date,
+};
+
+use crate::{AppState, feed::v2::FeedKind, metrics, upstream};
+
+const SIGNATURE_HEADER: &str = "x-webhook-signature";
+const DELIVERY_HEADER: &str = "x-webhook-delivery";
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60); // retry delays stop doubling here
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WebhookConfig {
+    pub endpoints: Vec<WebhookEndpoint>, // change detection only runs if any are configured
+    pub interval: u64,                   // seconds between checks for changes
+    pub retries: u32,                    // attempts after a failed delivery
+    pub retry_delay: u64,                // seconds before the first retry, doubled after each one
+    pub timeout: u64,                    // seconds per delivery attempt
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            endpoints: Vec::new(),
+            interval: 900,
+            retries: 3,
+            retry_delay: 10,
+            timeout: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookEndpoint {
+    pub url: String,
+    pub secret: Option<String>, // signs the body with HMAC-SHA256
+}
+
+const REDACTED: &str = "<redacted>";
+
+impl WebhookConfig {
+    /// A copy without the endpoint secrets, for printing.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for endpoint in &mut config.endpoints {
+            if endpoint.secret.is_some() {
+                endpoint.secret = Some(REDACTED.into());
+            }
+        }
+        config
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.interval == 0 {
+            anyhow::bail!("webhooks: interval must be at least one second");
+        }
+        if self.timeout == 0 {
+            anyhow::bail!("webhooks: timeout must be at least one second");
+        }
+        for endpoint in &self.endpoints {
+            let url = reqwest::Url::parse(&endpoint.url)
+                .with_context(|| format!("webhooks: invalid url \"{}\"", endpoint.url))?;
+            if url.scheme() != "http" && url.scheme() != "https" {
+                anyhow::bail!("webhooks: url \"{}\" is not http or https", endpoint.url);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Meal {
+    item_id: String,
+    dish_id: String,
+    name: String,
+    dish_type: String,
+    student_price: f64,
+    guest_price: f64,
+}
+
+type Menu = BTreeMap<chrono::NaiveDate, BTreeMap<String, Meal>>; // date:itemId:meal
+
+fn menu(menu_items: &[MenuItemsMenuItems]) -> anyhow::Result<Menu> {
+    let mut menu = Menu::new();
+    for item in menu_items {
+        let dish = &item.dish;
+        menu.entry(item_date(item)?).or_default().insert(
+            item.id.clone(),
+            Meal {
+                item_id: item.id.clone(),
+                dish_id: dish.id.clone(),
+                name: dish.name.clone(),
+                dish_type: dish_type_code(&dish.type_),
+                student_price: dish.student_price,
+                guest_price: dish.guest_price,
+            },
+        );
+    }
+    Ok(menu)
+}
+
+/// A change between two fetches of a canteen. A dish swapped within a menu item shows up as a
+/// changed meal, a dish replaced by a new menu item as a removed and an added meal.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Change {
+    DayPublished {
+        date: chrono::NaiveDate,
+        meals: Vec<Meal>,
+    },
+    DayClosed {
+        date: chrono::NaiveDate,
+        meals: Vec<Meal>, // as previously published
+    },
+    MealAdded {
+        date: chrono::NaiveDate,
+        meal: Meal,
+    },
+    MealRemoved {
+        date: chrono::NaiveDate,
+        meal: Meal,
+    },
+    MealChanged {
+        date: chrono::NaiveDate,
+        before: Meal,
+        after: Meal,
+    },
+}
+
+/// Compares two menus of a canteen. Days before `today` dropped out of the fetched range and are
+/// not reported as closed.
+fn diff(previous: &Menu, current: &Menu, today: chrono::NaiveDate) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for (date, meals) in previous.range(today..) {
+        if !current.contains_key(date) {
+            changes.push(Change::DayClosed {
+                date: *date,
+                meals: meals.values().cloned().collect(),
+            });
+        }
+    }
+
+    for (date, meals) in current {
+        let before = match previous.get(date) {
+            Some(v) => v,
+            None => {
+                changes.push(Change::DayPublished {
+                    date: *date,
+                    meals: meals.values().cloned().collect(),
+                });
+                continue;
+            }
+        };
+
+        for (item_id, meal) in before {
+            match meals.get(item_id) {
+                Some(after) if after != meal => changes.push(Change::MealChanged {
+                    date: *date,
+                    before: meal.clone(),
+                    after: after.clone(),
+                }),
+                Some(_) => {}
+                None => changes.push(Change::MealRemoved {
+                    date: *date,
+                    meal: meal.clone(),
+                }),
+            }
+        }
+        for (item_id, meal) in meals {
+            if !before.contains_key(item_id) {
+                changes.push(Change::MealAdded {
+                    date: *date,
+                    meal: meal.clone(),
+                });
+            }
+        }
+    }
+
+    changes.sort_by_key(|change| match change {
+        Change::DayPublished { date, .. }
+        | Change::DayClosed { date, .. }
+        | Change::MealAdded { date, .. }
+        | Change::MealRemoved { date, .. }
+        | Change::MealChanged { date, .. } => *date,
+    });
+    changes
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ChangeEvent {
+    event: &'static str,
+    canteen_id: String,
+    identifiers: Vec<String>,
+    detected_at: chrono::DateTime<chrono::Utc>,
+    changes: Vec<Change>,
+}
+
+impl ChangeEvent {
+    fn new(canteen_id: &str, identifiers: Vec<String>, changes: Vec<Change>) -> Self {
+        Self {
+            event: "menu.changed",
+            canteen_id: canteen_id.to_string(),
+            identifiers,
+            detected_at: chrono::Utc::now(),
+            changes,
+        }
+    }
+}
+
+/// The delay before the `attempt`th delivery attempt, doubling from `first` seconds up to
+/// [`MAX_RETRY_DELAY`].
+fn retry_delay(first: u64, attempt: u32) -> Duration {
+    let factor = 2u32
+        .checked_pow(attempt.saturating_sub(1))
+        .unwrap_or(u32::MAX);
+    Duration::from_secs(first)
+        .saturating_mul(factor)
+        .min(MAX_RETRY_DELAY)
+}
+
+/// The `x-webhook-signature` header value, the hex encoded HMAC-SHA256 of the body.
+fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+        .expect("hmac accepts keys of any length");
+    mac.update(body);
+    let digest = mac.finalize().into_bytes();
+    format!(
+        "sha256={}",
+        digest
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    )
+}
+
+/// Posts an event to an endpoint, retrying with exponential backoff on connection errors,
+/// server errors and rate limiting.
+async fn deliver(
+    client: reqwest::Client,
+    endpoint: WebhookEndpoint,
+    config: WebhookConfig,
+    delivery: String,
+    body: Vec<u8>,
+) {
+    let mut request = client
+        .post(&endpoint.url)
+        .timeout(Duration::from_secs(config.timeout))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(DELIVERY_HEADER, &delivery);
+    if let Some(secret) = &endpoint.secret {
+        request = request.header(SIGNATURE_HEADER, sign(secret, &body));
+    }
+    let request = request.body(body);
+
+    for attempt in 0..=config.retries {
+        if attempt > 0 {
+            tokio::time::sleep(retry_delay(config.retry_delay, attempt)).await;
+        }
+
+        let res = request
+            .try_clone()
+            .expect("the body is not a stream")
+            .send()
+            .await;
+        let retry = match res {
+            Ok(res) if res.status().is_success() => {
+                metrics::webhook_delivery("success");
+                tracing::debug!("delivered webhook {} to {}", delivery, endpoint.url);
+                return;
+            }
+            Ok(res) => {
+                let status = res.status();
+                tracing::warn!(
+                    "webhook {} to {} failed with status {}",
+                    delivery,
+                    endpoint.url,
+                    status
+                );
+                status.is_server_error()
+                    || status == reqwest::StatusCode::REQUEST_TIMEOUT
+                    || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            Err(e) => {
+                tracing::warn!("webhook {} to {} failed: {}", delivery, endpoint.url, e);
+                true
+            }
+        };
+        if !retry {
+            break;
+        }
+    }
+
+    metrics::webhook_delivery("error");
+    tracing::error!("giving up on webhook {} to {}", delivery, endpoint.url);
+}
+
+/// Fetches every canteen periodically and posts the changes since the previous fetch to the
+/// configured webhooks. The first fetch after startup only records the current menus.
+pub fn spawn(state: AppState) {
+    tokio::spawn(async move {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION")
+            ))
+            .build()
+            .expect("failed to build the webhook client");
+        let mut menus: HashMap<String, Menu> = HashMap::new(); // canteenId:menu
+
+        loop {
+            let canteens = state.canteens.get();
+            let config = &canteens.config.webhooks;
+
+            let mut canteen_ids: Vec<&String> = canteens.registered.values().collect();
+            canteen_ids.sort_unstable();
+            canteen_ids.dedup();
+            menus.retain(|id, _| canteen_ids.contains(&id) && !config.endpoints.is_empty());
+
+            let today = chrono::Local::now().date_naive();
+            let (from, to) = FeedKind::Full.range(today);
+            for canteen_id in canteen_ids.iter().filter(|_| !config.endpoints.is_empty()) {
+                let fetched = match upstream::fetch(&state, canteen_id, from, to).await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        tracing::warn!(
+                            "failed to fetch canteen {} for change detection: {:#}",
+                            canteen_id,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                // the last known good snapshot is not a new publication
+                if fetched.stale_age.is_some() {
+                    continue;
+                }
+                let menu = match menu(&fetched.menu_items) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        tracing::error!("failed to read menu items: {:?}", e);
+                        continue;
+                    }
+                };
+
+                let changes = match menus.insert(canteen_id.to_string(), menu) {
+                    Some(previous) => diff(&previous, &menus[*canteen_id], today),
+                    None => continue,
+                };
+                if changes.is_empty() {
+                    continue;
+                }
+
+                let mut identifiers: Vec<String> = canteens
+                    .registered
+                    .iter()
+                    .filter(|(_, id)| id == canteen_id)
+                    .map(|(identifier, _)| identifier.clone())
+                    .collect();
+                identifiers.sort_unstable();
+
+                let event = ChangeEvent::new(canteen_id, identifiers, changes);
+                tracing::info!(
+                    "detected {} menu changes of canteen {}",
+                    event.changes.len(),
+                    canteen_id
+                );
+                let body = match serde_json::to_vec(&event) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        tracing::error!("failed to serialize change event: {:?}", e);
+                        continue;
+                    }
+                };
+
+                // receivers can deduplicate retried deliveries by this id
+                let delivery = format!("{}-{}", canteen_id, event.detected_at.timestamp_millis());
+                for endpoint in &config.endpoints {
+                    tokio::spawn(deliver(
+                        client.clone(),
+                        endpoint.clone(),
+                        config.clone(),
+                        delivery.clone(),
+                        body.clone(),
+                    ));
+                }
+            }
+
+            tokio::time::sleep(Duration::from_secs(config.interval)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::VecDeque,
+        sync::{Arc, Mutex},
+    };
+
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, StatusCode},
+    };
+    use openmensa_parser_darmstadt::testing::menu_item;
+
+    use super::*;
+
+    /// Records the deliveries it receives and answers them with the given statuses, then 200.
+    #[derive(Clone, Default)]
+    struct Receiver {
+        statuses: Arc<Mutex<VecDeque<StatusCode>>>,
+        requests: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+    }
+
+    impl Receiver {
+        async fn start(statuses: &[u16]) -> (Self, String) {
+            let receiver = Self::default();
+            receiver.statuses.lock().unwrap().extend(
+                statuses
+                    .iter()
+                    .map(|status| StatusCode::from_u16(*status).unwrap()),
+            );
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            let app = axum::Router::new()
+                .route("/hook", axum::routing::post(receive))
+                .with_state(receiver.clone());
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+            (receiver, url)
+        }
+
+        fn requests(&self) -> Vec<(HeaderMap, Bytes)> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    async fn receive(
+        State(receiver): State<Receiver>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) -> StatusCode {
+        receiver.requests.lock().unwrap().push((headers, body));
+        let status = receiver.statuses.lock().unwrap().pop_front();
+        status.unwrap_or(StatusCode::OK)
+    }
+
+    fn config() -> WebhookConfig {
+        WebhookConfig {
+            retries: 3,
+            retry_delay: 0,
+            timeout: 5,
+            ..Default::default()
+        }
+    }
+
+    async fn deliver_to(url: &str, secret: Option<&str>, body: &[u8]) {
+        let endpoint = WebhookEndpoint {
+            url: url.to_string(),
+            secret: secret.map(str::to_string),
+        };
+        deliver(
+            reqwest::Client::new(),
+            endpoint,
+            config(),
+            "1-1".into(),
+            body.to_vec(),
+        )
+        .await;
+    }
+
+    fn date(date: &str) -> chrono::NaiveDate {
+        date.parse().unwrap()
+    }
+
+    fn meal(id: &str, date: &str, dish_id: &str, name: &str, price: f64) -> MenuItemsMenuItems {
+        menu_item(id, date, dish_id, name, price, price + 2.0)
+    }
+
+    #[test]
+    fn sign_is_the_hex_hmac_sha256() {
+        assert_eq!(
+            sign("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[tokio::test]
+    async fn deliveries_post_the_signed_event() {
+        let (receiver, url) = Receiver::start(&[]).await;
+        let previous = menu(&[meal("1", "2026-01-05", "10", "Chili", 3.5)]).unwrap();
+        let current = menu(&[meal("1", "2026-01-05", "10", "Chili", 3.8)]).unwrap();
+        let event = ChangeEvent::new(
+            "1",
+            vec!["stadtmitte".into()],
+            diff(&previous, &current, date("2026-01-05")),
+        );
+        let body = serde_json::to_vec(&event).unwrap();
+
+        deliver_to(&url, Some("secret"), &body).await;
+
+        let requests = receiver.requests();
+        assert_eq!(requests.len(), 1);
+        let (headers, received) = &requests[0];
+        assert_eq!(headers[DELIVERY_HEADER], "1-1");
+        assert_eq!(headers["content-type"], "application/json");
+
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+        let digest = signature.strip_prefix("sha256=").unwrap();
+        let digest = (0..digest.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&digest[i..i + 2], 16).unwrap())
+            .collect::<Vec<_>>();
+        let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(received);
+        mac.verify_slice(&digest).unwrap();
+
+        let payload: serde_json::Value = serde_json::from_slice(received).unwrap();
+        assert_eq!(payload["event"], "menu.changed");
+        assert_eq!(payload["canteenId"], "1");
+        assert_eq!(payload["identifiers"], serde_json::json!(["stadtmitte"]));
+        assert_eq!(payload["changes"][0]["type"], "mealChanged");
+        assert_eq!(payload["changes"][0]["date"], "2026-01-05");
+        assert_eq!(payload["changes"][0]["before"]["studentPrice"], 3.5);
+        assert_eq!(payload["changes"][0]["after"]["studentPrice"], 3.8);
+    }
+
+    #[tokio::test]
+    async fn deliveries_without_secret_are_unsigned() {
+        let (receiver, url) = Receiver::start(&[]).await;
+        deliver_to(&url, None, b"{}").await;
+
+        let requests = receiver.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(!requests[0].0.contains_key(SIGNATURE_HEADER));
+    }
+
+    #[tokio::test]
+    async fn server_errors_and_rate_limits_are_retried() {
+        let (receiver, url) = Receiver::start(&[503, 429, 500]).await;
+        deliver_to(&url, None, b"{}").await;
+        assert_eq!(receiver.requests().len(), 4);
+
+        // the same delivery on every attempt
+        let bodies: Vec<Bytes> = receiver.requests().into_iter().map(|(_, b)| b).collect();
+        assert!(bodies.iter().all(|b| b.as_ref() == b"{}"));
+    }
+
+    #[tokio::test]
+    async fn retries_give_up_after_the_configured_attempts() {
+        let (receiver, url) = Receiver::start(&[500, 500, 500, 500, 500]).await;
+        deliver_to(&url, None, b"{}").await;
+        assert_eq!(receiver.requests().len(), config().retries as usize + 1);
+    }
+
+    #[test]
+    fn retry_delays_double_up_to_the_maximum() {
+        assert_eq!(retry_delay(10, 1), Duration::from_secs(10));
+        assert_eq!(retry_delay(10, 2), Duration::from_secs(20));
+        assert_eq!(retry_delay(10, 4), Duration::from_secs(80));
+        assert_eq!(retry_delay(10, 20), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(10, u32::MAX), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(u64::MAX, 1), MAX_RETRY_DELAY);
+        assert_eq!(retry_delay(0, 40), Duration::ZERO);
+    }
+
+    #[test]
+    fn validate_rejects_zero_intervals_and_timeouts() {
+        assert!(config().validate().is_ok());
+        let interval = WebhookConfig {
+            interval: 0,
+            ..config()
+        };
+        assert!(interval.validate().is_err());
+        let timeout = WebhookConfig {
+            timeout: 0,
+            ..config()
+        };
+        assert!(timeout.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let (receiver, url) = Receiver::start(&[400]).await;
+        deliver_to(&url, None, b"{}").await;
+        assert_eq!(receiver.requests().len(), 1);
+    }
+
+    #[test]
+    fn diff_reports_closed_days_but_not_past_ones() {
+        let previous = menu(&[
+            meal("1", "2026-01-04", "10", "Chili", 3.5),
+            meal("2", "2026-01-05", "10", "Chili", 3.5),
+            meal("3", "2026-01-06", "11", "Gulasch", 4.0),
+        ])
+        .unwrap();
+        let current = menu(&[meal("3", "2026-01-06", "11", "Gulasch", 4.0)]).unwrap();
+
+        let changes = diff(&previous, &current, date("2026-01-05"));
+        assert_eq!(changes.len(), 1);
+        match &changes[0] {
+            Change::DayClosed {
+                date: closed,
+                meals,
+            } => {
+                assert_eq!(*closed, date("2026-01-05"));
+                assert_eq!(meals.len(), 1);
+                assert_eq!(meals[0].name, "Chili");
+            }
+            change => panic!("unexpected change {:?}", change),
+        }
+    }
+
+    #[test]
+    fn diff_reports_swapped_dishes() {
+        let previous = menu(&[
+            meal("1", "2026-01-05", "10", "Chili", 3.5),
+            meal("2", "2026-01-05", "11", "Gulasch", 4.0),
+        ])
+        .unwrap();
+        // item 1 now serves another dish, item 2 was replaced by a new item
+        let current = menu(&[
+            meal("1", "2026-01-05", "12", "Linsen", 3.5),
+            meal("3", "2026-01-05", "13", "Pasta", 3.0),
+        ])
+        .unwrap();
+
+        let changes = diff(&previous, &current, date("2026-01-05"));
+        assert_eq!(changes.len(), 3);
+        assert!(changes.iter().any(|c| matches!(c,
+            Change::MealChanged { before, after, .. }
+                if before.dish_id == "10" && after.dish_id == "12"
+        )));
+        assert!(changes.iter().any(|c| matches!(c,
+            Change::MealRemoved { meal, .. } if meal.dish_id == "11"
+        )));
+        assert!(changes.iter().any(|c| matches!(c,
+            Change::MealAdded { meal, .. } if meal.dish_id == "13"
+        )));
+    }
+
+    #[test]
+    fn diff_reports_price_changes_of_each_item() {
+        // the same dish twice on one day, as two menu items
+        let previous = menu(&[
+            meal("1", "2026-01-05", "10", "Chili", 3.5),
+            meal("2", "2026-01-05", "10", "Chili", 3.5),
+        ])
+        .unwrap();
+        let current = menu(&[
+            meal("1", "2026-01-05", "10", "Chili", 3.5),
+            meal("2", "2026-01-05", "10", "Chili", 3.8),
+            meal("3", "2026-01-06", "10", "Chili", 3.8),
+        ])
+        .unwrap();
+
+        let changes = diff(&previous, &current, date("2026-01-05"));
+        assert_eq!(changes.len(), 2);
+        match &changes[0] {
+            Change::MealChanged { before, after, .. } => {
+                assert_eq!(before.item_id, "2");
+                assert_eq!(before.student_price, 3.5);
+                assert_eq!(after.student_price, 3.8);
+            }
+            change => panic!("unexpected change {:?}", change),
+        }
+        assert!(matches!(&changes[1], Change::DayPublished { meals, .. } if meals.len() == 1));
+        assert!(diff(&current, &current, date("2026-01-05")).is_empty());
+    }
+}